@@ -0,0 +1,64 @@
+//! Compares the `FreeRanges` and `VecFreeRanges` backends under churn: a
+//! pool holding a steady number of live indices, scattered among as many
+//! free ones, frees random indices and allocates replacements.
+//!
+//! Run with `cargo bench --bench backend_churn`.
+
+extern crate index_pool;
+use index_pool::backend::VecFreeRanges;
+use index_pool::{FreeListBackend, FreeRanges, IndexPool};
+
+use std::time::{Duration, Instant};
+
+/// A tiny xorshift generator so every backend sees the same operations
+/// without pulling in a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Allocates `2 * live` indices and frees a random half of them, then
+/// runs `ops` rounds of freeing a random live index and allocating a new
+/// one. Returns the time taken and the average number of free ranges.
+fn churn<B: FreeListBackend>(live: usize, ops: usize) -> (Duration, usize) {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let mut pool = IndexPool::<B>::with_backend();
+    let mut ids: Vec<usize> = (0..2 * live).map(|_| pool.new_id()).collect();
+    for _ in 0..live {
+        let slot = rng.below(ids.len());
+        pool.return_id(ids.swap_remove(slot)).unwrap();
+    }
+    let mut ranges = 0;
+
+    let start = Instant::now();
+    for _ in 0..ops {
+        let slot = rng.below(ids.len());
+        pool.return_id(ids.swap_remove(slot)).unwrap();
+        ids.push(pool.new_id());
+        ranges += pool.free_ranges_count();
+    }
+    (start.elapsed(), ranges / ops)
+}
+
+fn main() {
+    let ops = 1_000_000;
+    for &live in &[256, 4096, 16384, 65536] {
+        let (btree, btree_ranges) = churn::<FreeRanges>(live, ops);
+        let (vec, vec_ranges) = churn::<VecFreeRanges>(live, ops);
+        assert_eq!(btree_ranges, vec_ranges);
+        println!(
+            "{:>6} live, ~{:>4} free ranges: FreeRanges {:>8.2?}, VecFreeRanges {:>8.2?}",
+            live, btree_ranges, btree, vec
+        );
+    }
+}
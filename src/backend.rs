@@ -0,0 +1,297 @@
+//! Storage for an `IndexPool`'s free list.
+//!
+//! `IndexPool` keeps its free indices as a sorted set of disjoint ranges.
+//! By default these live in a `FreeRanges` (a `BTreeSet`), but any type
+//! implementing `FreeListBackend` can be used instead, e.g. the
+//! `VecFreeRanges` in this module, which is quicker while there are only a
+//! few dozen ranges.
+
+use std::collections::btree_set;
+use std::fmt;
+use std::slice;
+
+use {FreeRanges, Range};
+
+/// A set of free indices, stored as sorted, disjoint, inclusive ranges.
+/// Ranges which touch must be merged as they're freed, so that iteration
+/// always yields the fewest ranges possible.
+pub trait FreeListBackend: Clone + Default + fmt::Debug {
+    /// Iterator over every free range, in ascending order
+    type Iter<'a>: DoubleEndedIterator<Item = &'a Range> + ExactSizeIterator + Clone
+    where
+        Self: 'a;
+
+    /// Iterator over the free ranges from a given index onwards
+    type IterAfter<'a>: Iterator<Item = &'a Range> + Clone
+    where
+        Self: 'a;
+
+    /// Iterates over all of the free ranges, in ascending order
+    fn free_ranges(&self) -> Self::Iter<'_>;
+
+    /// Iterates over the free ranges starting from the one which contains
+    /// `start`, or the first one after it if `start` isn't free
+    fn free_ranges_after(&self, start: usize) -> Self::IterAfter<'_>;
+
+    /// Marks `index` as free. Returns false if it was already free.
+    fn set_free(&mut self, index: usize) -> bool;
+
+    /// Marks every index in `range` as free, merging it with any ranges it
+    /// touches. Returns false if the whole range was already free.
+    fn set_range_free(&mut self, range: Range) -> bool;
+
+    /// Marks a free `index` as used. Returns false if it wasn't free.
+    fn set_used(&mut self, index: usize) -> bool;
+
+    /// Marks the lowest free index as used and returns it
+    fn set_first_used(&mut self) -> Option<usize>;
+
+    /// Returns the highest free index
+    fn last(&self) -> Option<usize>;
+
+    /// Removes the highest free range entirely
+    fn remove_last_contiguous(&mut self);
+
+    /// Checks whether `index` is free
+    fn is_free(&self, index: usize) -> bool;
+
+    /// Marks every index as used
+    fn clear(&mut self);
+}
+
+impl FreeListBackend for FreeRanges {
+    type Iter<'a> = btree_set::Iter<'a, Range>;
+    type IterAfter<'a> = btree_set::Range<'a, Range>;
+
+    #[inline]
+    fn free_ranges(&self) -> Self::Iter<'_> {
+        FreeRanges::free_ranges(self)
+    }
+
+    #[inline]
+    fn free_ranges_after(&self, start: usize) -> Self::IterAfter<'_> {
+        FreeRanges::free_ranges_after(self, start)
+    }
+
+    #[inline]
+    fn set_free(&mut self, index: usize) -> bool {
+        FreeRanges::set_free(self, index)
+    }
+
+    fn set_range_free(&mut self, range: Range) -> bool {
+        // `FreeRanges` only merges a range with its immediate neighbours, so
+        // free the used gaps within `range` one at a time instead of letting
+        // it swallow the free ranges it spans
+        let mut start = range.min;
+        let mut freed = false;
+        loop {
+            let next = FreeRanges::free_ranges_after(self, start).next().cloned();
+            match next {
+                Some(free) if free.min <= range.max => {
+                    if free.min > start {
+                        FreeRanges::set_range_free(
+                            self,
+                            Range {
+                                min: start,
+                                max: free.min - 1,
+                            },
+                        );
+                        freed = true;
+                    }
+                    if free.max >= range.max {
+                        return freed;
+                    }
+                    start = free.max + 1;
+                }
+                _ => {
+                    FreeRanges::set_range_free(
+                        self,
+                        Range {
+                            min: start,
+                            max: range.max,
+                        },
+                    );
+                    return true;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn set_used(&mut self, index: usize) -> bool {
+        FreeRanges::set_used(self, index)
+    }
+
+    #[inline]
+    fn set_first_used(&mut self) -> Option<usize> {
+        FreeRanges::set_first_used(self)
+    }
+
+    #[inline]
+    fn last(&self) -> Option<usize> {
+        FreeRanges::last(self)
+    }
+
+    #[inline]
+    fn remove_last_contiguous(&mut self) {
+        FreeRanges::remove_last_contiguous(self)
+    }
+
+    #[inline]
+    fn is_free(&self, index: usize) -> bool {
+        FreeRanges::is_free(self, index)
+    }
+
+    #[inline]
+    fn clear(&mut self) {
+        FreeRanges::clear(self)
+    }
+}
+
+/// A free list backed by a sorted `Vec` of ranges, searched with a binary
+/// search. Splitting or removing a range shifts every range after it, so
+/// this only beats `FreeRanges` while there are a few dozen ranges; in
+/// `benches/backend_churn.rs` it's already slower at around a hundred, and
+/// an order of magnitude slower at a couple of thousand. Fragmented pools
+/// should stick with the default.
+#[derive(Debug, Default)]
+pub struct VecFreeRanges {
+    ranges: Vec<Range>,
+}
+
+impl Clone for VecFreeRanges {
+    #[inline]
+    fn clone(&self) -> Self {
+        VecFreeRanges {
+            ranges: self.ranges.clone(),
+        }
+    }
+
+    /// Copies `source`'s ranges into this list's existing buffer
+    #[inline]
+    fn clone_from(&mut self, source: &Self) {
+        self.ranges.clone_from(&source.ranges);
+    }
+}
+
+impl VecFreeRanges {
+    /// Starts empty with no ranges free
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The position of the first range which ends at or after `index`
+    #[inline]
+    fn search(&self, index: usize) -> usize {
+        self.ranges.partition_point(|range| range.max < index)
+    }
+
+    /// The position of the range containing `index`, if it is free
+    #[inline]
+    fn find(&self, index: usize) -> Option<usize> {
+        let i = self.search(index);
+        match self.ranges.get(i) {
+            Some(range) if range.min <= index => Some(i),
+            _ => None,
+        }
+    }
+}
+
+impl FreeListBackend for VecFreeRanges {
+    type Iter<'a> = slice::Iter<'a, Range>;
+    type IterAfter<'a> = slice::Iter<'a, Range>;
+
+    #[inline]
+    fn free_ranges(&self) -> Self::Iter<'_> {
+        self.ranges.iter()
+    }
+
+    #[inline]
+    fn free_ranges_after(&self, start: usize) -> Self::IterAfter<'_> {
+        self.ranges[self.search(start)..].iter()
+    }
+
+    #[inline]
+    fn set_free(&mut self, index: usize) -> bool {
+        self.set_range_free(Range::id(index))
+    }
+
+    fn set_range_free(&mut self, range: Range) -> bool {
+        if let Some(i) = self.find(range.min) {
+            if self.ranges[i].max >= range.max {
+                return false;
+            }
+        }
+
+        // Every range which overlaps or touches `range` gets merged into it
+        let start = self
+            .ranges
+            .partition_point(|free| free.max < range.min.saturating_sub(1));
+        let end = self
+            .ranges
+            .partition_point(|free| free.min.saturating_sub(1) <= range.max);
+
+        let mut merged = range;
+        if start < end {
+            merged.min = merged.min.min(self.ranges[start].min);
+            merged.max = merged.max.max(self.ranges[end - 1].max);
+        }
+        self.ranges.splice(start..end, Some(merged));
+        true
+    }
+
+    fn set_used(&mut self, index: usize) -> bool {
+        let i = match self.find(index) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        let range = self.ranges[i];
+        match (range.min < index, index < range.max) {
+            (true, true) => {
+                self.ranges[i].max = index - 1;
+                self.ranges.insert(
+                    i + 1,
+                    Range {
+                        min: index + 1,
+                        max: range.max,
+                    },
+                );
+            }
+            (true, false) => self.ranges[i].max = index - 1,
+            (false, true) => self.ranges[i].min = index + 1,
+            (false, false) => {
+                self.ranges.remove(i);
+            }
+        }
+        true
+    }
+
+    #[inline]
+    fn set_first_used(&mut self) -> Option<usize> {
+        let index = self.ranges.first()?.min;
+        self.set_used(index);
+        Some(index)
+    }
+
+    #[inline]
+    fn last(&self) -> Option<usize> {
+        self.ranges.last().map(|range| range.max)
+    }
+
+    #[inline]
+    fn remove_last_contiguous(&mut self) {
+        self.ranges.pop();
+    }
+
+    #[inline]
+    fn is_free(&self, index: usize) -> bool {
+        self.find(index).is_some()
+    }
+
+    #[inline]
+    fn clear(&mut self) {
+        self.ranges.clear();
+    }
+}
@@ -0,0 +1,106 @@
+//! Shared, single-threaded access to an `IndexPool`.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::rc::Rc;
+
+use lease::SharedBlockLease;
+use {AlreadyInUse, AlreadyReturned, IndexPool};
+
+/// A cloneable handle to a shared `IndexPool`. Every clone refers to the
+/// same underlying pool, so an index allocated through one handle is
+/// visible through all of the others.
+///
+/// This is an `Rc<RefCell<IndexPool>>` under the hood, so it is only
+/// suitable for sharing a pool within a single thread. Each method
+/// borrows the pool for the duration of the call only.
+#[derive(Clone, Debug, Default)]
+pub struct PoolHandle {
+    pool: Rc<RefCell<IndexPool>>,
+}
+
+impl PoolHandle {
+    /// Constructs a handle to a new, empty pool.
+    #[inline]
+    pub fn new() -> Self {
+        PoolHandle::from_pool(IndexPool::new())
+    }
+
+    /// Wraps an existing pool so that it can be shared.
+    #[inline]
+    pub fn from_pool(pool: IndexPool) -> Self {
+        PoolHandle {
+            pool: Rc::new(RefCell::new(pool)),
+        }
+    }
+
+    /// See `IndexPool::new_id`
+    #[inline]
+    pub fn new_id(&self) -> usize {
+        self.pool.borrow_mut().new_id()
+    }
+
+    /// See `IndexPool::request_id`
+    #[inline]
+    pub fn request_id(&self, id: usize) -> Result<(), AlreadyInUse> {
+        self.pool.borrow_mut().request_id(id)
+    }
+
+    /// See `IndexPool::return_id`
+    #[inline]
+    pub fn return_id(&self, id: usize) -> Result<(), AlreadyReturned> {
+        self.pool.borrow_mut().return_id(id)
+    }
+
+    /// See `IndexPool::maximum`
+    #[inline]
+    pub fn maximum(&self) -> usize {
+        self.pool.borrow().maximum()
+    }
+
+    /// See `IndexPool::in_use`
+    #[inline]
+    pub fn in_use(&self) -> usize {
+        self.pool.borrow().in_use()
+    }
+
+    /// See `IndexPool::is_free`
+    #[inline]
+    pub fn is_free(&self, id: usize) -> bool {
+        self.pool.borrow().is_free(id)
+    }
+
+    /// See `IndexPool::lease_contiguous`. The lease keeps its own handle to
+    /// the pool, so the pool stays usable while it's alive.
+    #[inline]
+    pub fn lease_contiguous(&self, n: usize) -> SharedBlockLease {
+        let range = self.pool.borrow_mut().reserve_range_or_grow(n);
+        SharedBlockLease::new(self.clone(), range)
+    }
+
+    /// Borrows the underlying pool, e.g. to iterate over its indices.
+    /// Panics if the pool is currently mutably borrowed.
+    #[inline]
+    pub fn borrow(&self) -> Ref<'_, IndexPool> {
+        self.pool.borrow()
+    }
+
+    /// Mutably borrows the underlying pool for operations which aren't
+    /// forwarded by the handle. Panics if the pool is already borrowed.
+    #[inline]
+    pub fn borrow_mut(&self) -> RefMut<'_, IndexPool> {
+        self.pool.borrow_mut()
+    }
+
+    /// Returns true if both handles refer to the same pool.
+    #[inline]
+    pub fn same_pool(&self, other: &PoolHandle) -> bool {
+        Rc::ptr_eq(&self.pool, &other.pool)
+    }
+}
+
+impl From<IndexPool> for PoolHandle {
+    #[inline]
+    fn from(pool: IndexPool) -> Self {
+        PoolHandle::from_pool(pool)
+    }
+}
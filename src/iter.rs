@@ -1,19 +1,18 @@
-use std::collections::btree_set;
-use std::collections::btree_set::Iter as BIter;
 use std::iter::Cloned;
+use std::ops;
 
-use Range;
+use {FreeListBackend, FreeRanges, Range};
 
 #[derive(Clone)]
-pub struct IndexIter<'a> {
-    free_ranges: Cloned<BIter<'a, Range>>,
+pub struct IndexIter<'a, B: FreeListBackend + 'a = FreeRanges> {
+    free_ranges: Cloned<B::Iter<'a>>,
     next_range: Option<Range>,
     index: usize,
     end: usize,
 }
 
-impl<'a> IndexIter<'a> {
-    pub(crate) fn new(free_ranges: BIter<'a, Range>, end: usize) -> IndexIter<'a> {
+impl<'a, B: FreeListBackend> IndexIter<'a, B> {
+    pub(crate) fn new(free_ranges: B::Iter<'a>, end: usize) -> IndexIter<'a, B> {
         let mut free_ranges = free_ranges.cloned();
         let mut first_range = free_ranges.next();
 
@@ -32,13 +31,32 @@ impl<'a> IndexIter<'a> {
             end,
         }
     }
+
+    /// Returns the iterator's current cursor and end without advancing it.
+    /// Every in-use index that has yet to be yielded lies in this range.
+    #[inline]
+    pub fn remaining(&self) -> ops::Range<usize> {
+        self.index..self.end
+    }
+
+    /// Returns the index `next()` would yield, without advancing. The
+    /// cursor always rests on an in-use index, so this is cheaper than
+    /// cloning the iterator to look ahead.
+    #[inline]
+    pub fn peek_next(&self) -> Option<usize> {
+        if self.index < self.end {
+            Some(self.index)
+        } else {
+            None
+        }
+    }
 }
 
-impl<'a> Iterator for IndexIter<'a> {
+impl<'a, B: FreeListBackend> Iterator for IndexIter<'a, B> {
     type Item = usize;
     #[inline]
     fn next(&mut self) -> Option<usize> {
-        if self.index == self.end {
+        if self.index >= self.end {
             return None;
         }
 
@@ -57,15 +75,19 @@ impl<'a> Iterator for IndexIter<'a> {
 }
 
 #[derive(Clone)]
-pub struct IndexAfterIter<'a> {
-    free_ranges: Cloned<btree_set::Range<'a, Range>>,
+pub struct IndexAfterIter<'a, B: FreeListBackend + 'a = FreeRanges> {
+    free_ranges: Cloned<B::IterAfter<'a>>,
     next_range: Option<Range>,
     index: usize,
     end: usize,
 }
 
-impl<'a> IndexAfterIter<'a> {
-    pub(crate) fn new(free_ranges: btree_set::Range<'a, Range>, start: usize, end: usize) -> IndexAfterIter<'a> {
+impl<'a, B: FreeListBackend> IndexAfterIter<'a, B> {
+    pub(crate) fn new(
+        free_ranges: B::IterAfter<'a>,
+        start: usize,
+        end: usize,
+    ) -> IndexAfterIter<'a, B> {
         let mut free_ranges = free_ranges.cloned();
         let mut first_range = free_ranges.next();
 
@@ -86,12 +108,12 @@ impl<'a> IndexAfterIter<'a> {
     }
 }
 
-impl<'a> Iterator for IndexAfterIter<'a> {
+impl<'a, B: FreeListBackend> Iterator for IndexAfterIter<'a, B> {
     type Item = usize;
 
     #[inline]
     fn next(&mut self) -> Option<usize> {
-        if self.index == self.end {
+        if self.index >= self.end {
             return None;
         }
 
@@ -108,3 +130,50 @@ impl<'a> Iterator for IndexAfterIter<'a> {
         Some(value)
     }
 }
+
+#[derive(Clone)]
+pub struct UsedRangeIter<'a, B: FreeListBackend + 'a = FreeRanges> {
+    free_ranges: Cloned<B::Iter<'a>>,
+    index: usize,
+    end: usize,
+}
+
+impl<'a, B: FreeListBackend> UsedRangeIter<'a, B> {
+    pub(crate) fn new(free_ranges: B::Iter<'a>, end: usize) -> UsedRangeIter<'a, B> {
+        UsedRangeIter {
+            free_ranges: free_ranges.cloned(),
+            index: 0,
+            end,
+        }
+    }
+}
+
+impl<'a, B: FreeListBackend> Iterator for UsedRangeIter<'a, B> {
+    type Item = Range;
+
+    #[inline]
+    fn next(&mut self) -> Option<Range> {
+        while self.index < self.end {
+            let start = self.index;
+            let stop = match self.free_ranges.next() {
+                Some(range) => {
+                    self.index = range.max + 1;
+                    range.min
+                }
+                None => {
+                    self.index = self.end;
+                    self.end
+                }
+            };
+
+            if stop > start {
+                return Some(Range {
+                    min: start,
+                    max: stop - 1,
+                });
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,125 @@
+//! RAII guards which give a block of indices back to the pool when they
+//! go out of scope.
+
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+
+use {FreeListBackend, FreeRanges, IndexPool, PoolHandle, Range};
+
+/// A block of contiguous indices allocated by
+/// `IndexPool::lease_contiguous`, which is freed when the lease is
+/// dropped, including during a panic. The lease borrows the pool, but
+/// `pool` gives access to it in the meantime. Derefs to the allocated
+/// `Range`.
+///
+/// Only the block's indices which are still in use are freed on drop, so
+/// returning some of them early is harmless, but don't hand them out
+/// again before the lease ends.
+pub struct BlockLease<'a, B: FreeListBackend + 'a = FreeRanges> {
+    pool: &'a mut IndexPool<B>,
+    range: Range,
+}
+
+impl<'a, B: FreeListBackend> BlockLease<'a, B> {
+    pub(crate) fn new(pool: &'a mut IndexPool<B>, range: Range) -> Self {
+        BlockLease { pool, range }
+    }
+
+    /// The indices which were allocated for this lease
+    #[inline]
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// The pool the block was allocated from, e.g. to allocate more
+    /// indices while the lease is held
+    #[inline]
+    pub fn pool(&mut self) -> &mut IndexPool<B> {
+        self.pool
+    }
+
+    /// Ends the lease without freeing the block, returning its indices.
+    /// They stay in use until given back some other way.
+    #[inline]
+    pub fn into_range(self) -> Range {
+        let range = self.range;
+        mem::forget(self);
+        range
+    }
+}
+
+impl<'a, B: FreeListBackend> Deref for BlockLease<'a, B> {
+    type Target = Range;
+
+    #[inline]
+    fn deref(&self) -> &Range {
+        &self.range
+    }
+}
+
+impl<'a, B: FreeListBackend> Drop for BlockLease<'a, B> {
+    fn drop(&mut self) {
+        // Indices freed through `pool()` in the meantime mustn't be freed
+        // a second time
+        self.pool.release_used_within(self.range);
+    }
+}
+
+impl<'a, B: FreeListBackend> fmt::Debug for BlockLease<'a, B> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("BlockLease")
+            .field("range", &self.range)
+            .finish()
+    }
+}
+
+/// Like `BlockLease`, but holds a `PoolHandle` rather than borrowing the
+/// pool, so other handles can keep using the pool while the lease is
+/// alive. Returned from `PoolHandle::lease_contiguous`.
+#[derive(Debug)]
+pub struct SharedBlockLease {
+    handle: Option<PoolHandle>,
+    range: Range,
+}
+
+impl SharedBlockLease {
+    pub(crate) fn new(handle: PoolHandle, range: Range) -> Self {
+        SharedBlockLease {
+            handle: Some(handle),
+            range,
+        }
+    }
+
+    /// The indices which were allocated for this lease
+    #[inline]
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// Ends the lease without freeing the block, returning its indices.
+    /// They stay in use until given back some other way.
+    #[inline]
+    pub fn into_range(mut self) -> Range {
+        self.handle = None;
+        self.range
+    }
+}
+
+impl Deref for SharedBlockLease {
+    type Target = Range;
+
+    #[inline]
+    fn deref(&self) -> &Range {
+        &self.range
+    }
+}
+
+impl Drop for SharedBlockLease {
+    /// Panics if the pool is borrowed through another handle at the time.
+    fn drop(&mut self) {
+        if let Some(ref handle) = self.handle {
+            handle.borrow_mut().release_used_within(self.range);
+        }
+    }
+}
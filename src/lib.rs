@@ -29,24 +29,58 @@
 
 extern crate free_ranges;
 
-use free_ranges::FreeRanges;
-use free_ranges::Range;
+pub use free_ranges::{FreeRanges, Range};
 
+use std::cmp::{self, Ordering};
 use std::error::Error;
 use std::fmt;
-use std::usize;
+use std::mem;
 
+pub use backend::FreeListBackend;
+pub use handle::PoolHandle;
+
+pub mod backend;
+pub mod handle;
 pub mod iter;
+pub mod lease;
+pub mod typed;
 
 /// A pool which manages allocation of unique indices. Acts like a
 /// psuedo-memory allocator.
-#[derive(Debug)]
-pub struct IndexPool {
+///
+/// The free list is stored in a `FreeRanges` unless another
+/// `FreeListBackend` is chosen with `with_backend`.
+pub struct IndexPool<B = FreeRanges> {
     next_id: usize,
     in_use: usize,
-    free_list: FreeRanges,
+    prefix: usize,
+    reserved: usize,
+    limit: usize,
+    free_list: B,
+    allocation_seq: Option<Vec<u64>>,
+    next_seq: u64,
+    on_allocate: Option<Callback>,
+    on_free: Option<Callback>,
+    max_free_ranges: usize,
+    on_fragmented: Option<Callback>,
+    fragmented: bool,
+    on_grow: Option<Callback>,
+    grown_to: usize,
+    defer_grow: bool,
+    auto_collapse: bool,
+    defer_collapse: bool,
+    peak_in_use: usize,
+    peak_maximum: usize,
+    total_allocations: u64,
+    total_frees: u64,
+    recycled_allocations: u64,
+    used_cursor: usize,
 }
 
+/// A callback registered with the pool, which is passed an index. It must
+/// be `Send + Sync` so that the pool itself stays `Send + Sync`.
+pub type Callback = Box<dyn FnMut(usize) + Send + Sync>;
+
 impl IndexPool {
     /// Constructs an empty IndexPool. Indices will start at `0`.
     #[inline]
@@ -62,79 +96,808 @@ impl IndexPool {
     /// kind of performance penalty, except that `in_use()` will
     /// include the `[0..index)` range.
     pub fn with_initial_index(index: usize) -> Self {
+        Self::empty(index)
+    }
+
+    /// Constructs an empty IndexPool which will only ever hand out
+    /// indices in `[0..limit)`. Use `try_new_id` and `checked_request_id`
+    /// to find out when the limit has been reached.
+    pub fn with_limit(limit: usize) -> Self {
+        let mut pool = IndexPool::new();
+        pool.limit = limit;
+        pool
+    }
+
+    /// Constructs a pool where exactly the indices in `ids` are in use.
+    /// `ids` must be strictly ascending, which lets the free ranges be
+    /// built from the gaps directly in a single pass instead of going
+    /// through `request_id` for every index.
+    ///
+    /// Panics if `ids` contains `usize::MAX`, which `request_id` rejects
+    /// too, as `maximum()` would have to lie past it.
+    pub fn from_sorted_used(ids: &[usize]) -> Self {
+        debug_assert!(
+            ids.windows(2).all(|w| w[0] < w[1]),
+            "ids must be strictly ascending"
+        );
+
+        let mut pool = IndexPool::new();
+        let mut next = 0;
+        for &id in ids {
+            assert!(id < usize::MAX, "usize::MAX can't be used as an index");
+            if id > next {
+                pool.free_list.set_range_free(Range {
+                    min: next,
+                    max: id - 1,
+                });
+            }
+            next = id + 1;
+        }
+        pool.next_id = next;
+        pool.in_use = ids.len();
+        pool
+    }
+
+    /// Rebuilds a pool from storage indexed by it, e.g. after loading a
+    /// `Vec<Option<T>>`: slots holding `Some` are in use and slots holding
+    /// `None` are free. Trailing `None`s aren't part of the pool, so
+    /// `maximum()` is one past the last `Some`.
+    pub fn from_option_slice<T>(data: &[Option<T>]) -> Self {
+        let mut pool = IndexPool::new();
+        let mut next = 0;
+        for (id, slot) in data.iter().enumerate() {
+            if slot.is_none() {
+                continue;
+            }
+            if id > next {
+                pool.free_list.set_range_free(Range {
+                    min: next,
+                    max: id - 1,
+                });
+            }
+            next = id + 1;
+            pool.in_use += 1;
+        }
+        pool.next_id = next;
+        pool
+    }
+
+    /// Reconstructs a pool from the `maximum()` and free ranges written by
+    /// `write_ranges_to`. The ranges are inclusive `(min, max)` pairs and
+    /// must be sorted and non-overlapping, with every index below
+    /// `next_id`; otherwise `InvalidState` is returned. Any free range at
+    /// the end is folded back into the tail as usual.
+    pub fn read_ranges_from(next_id: usize, buf: &[(usize, usize)]) -> Result<Self, InvalidState> {
+        let mut pool = IndexPool::new();
+        let mut free_count = 0;
+        let mut prev_max = None;
+        for &(min, max) in buf {
+            if min > max || max >= next_id || prev_max.map_or(false, |prev| min <= prev) {
+                return Err(InvalidState);
+            }
+            pool.free_list.set_range_free(Range { min, max });
+            free_count += max - min + 1;
+            prev_max = Some(max);
+        }
+
+        pool.next_id = next_id;
+        pool.in_use = next_id - free_count;
+        while pool.collapse_next() {}
+        Ok(pool)
+    }
+
+    /// Constructs an empty IndexPool which only ever hands out indices in
+    /// `[lo..hi)`, e.g. so that several pools can share one id space with
+    /// each owning a disjoint window of it. This combines
+    /// `with_initial_index(lo)` and `with_limit(hi)`, so `[0..lo)` counts
+    /// as in use and `try_new_id` returns `PoolFull` once the window is
+    /// exhausted.
+    ///
+    /// Panics if `lo > hi`.
+    pub fn windowed(lo: usize, hi: usize) -> Self {
+        assert!(lo <= hi, "the window's start is past its end");
+        let mut pool = IndexPool::with_initial_index(lo);
+        pool.limit = hi;
+        pool
+    }
+}
+
+impl<B: FreeListBackend> IndexPool<B> {
+    /// Constructs an empty IndexPool which stores its free list in a `B`.
+    /// Indices will start at `0`.
+    #[inline]
+    pub fn with_backend() -> Self {
+        Self::empty(0)
+    }
+
+    /// Constructs a pool from `maximum()` and its free ranges, moving
+    /// `free_ranges` in as-is rather than inserting the ranges one by one.
+    /// This is the inverse of `into_free_ranges`. Every free range must lie
+    /// below `next_id`, and touching ranges must have been merged, which
+    /// the backend does as ranges are freed; otherwise `InvalidState` is
+    /// returned. Any free range at the end is folded back into the tail as
+    /// usual.
+    pub fn from_raw_parts(next_id: usize, free_ranges: B) -> Result<Self, InvalidState> {
+        let mut pool = Self::empty(0);
+        pool.free_list = free_ranges;
+        pool.next_id = next_id;
+        if !pool.check_range_invariants() {
+            return Err(InvalidState);
+        }
+
+        let free_count: usize = pool.gap_sizes().sum();
+        pool.in_use = next_id - free_count;
+        pool.grown_to = next_id;
+        while pool.collapse_next() {}
+        Ok(pool)
+    }
+
+    fn empty(index: usize) -> Self {
         IndexPool {
             next_id: index,
-            in_use: 0,
-            free_list: FreeRanges::new(),
+            in_use: index,
+            prefix: index,
+            reserved: 0,
+            limit: usize::MAX,
+            free_list: B::default(),
+            allocation_seq: None,
+            next_seq: 1,
+            on_allocate: None,
+            on_free: None,
+            max_free_ranges: usize::MAX,
+            on_fragmented: None,
+            fragmented: false,
+            on_grow: None,
+            grown_to: index,
+            defer_grow: false,
+            auto_collapse: true,
+            defer_collapse: false,
+            peak_in_use: 0,
+            peak_maximum: 0,
+            total_allocations: 0,
+            total_frees: 0,
+            recycled_allocations: 0,
+            used_cursor: 0,
+        }
+    }
+
+    /// Returns the upper bound passed to `with_limit`, if there is one
+    #[inline]
+    pub fn limit(&self) -> Option<usize> {
+        if self.limit == usize::MAX {
+            None
+        } else {
+            Some(self.limit)
         }
     }
 
     /// Allocates a new index for use. This is guaranteed to not be any index
     /// which has previously been returned from `new_id` but has not yet been
     /// passed to `return_id`.
+    ///
+    /// Panics if the pool has a limit and every index below it is in use.
     #[inline]
     pub fn new_id(&mut self) -> usize {
+        self.reserve_lowest()
+    }
+
+    /// Allocates two distinct indices, e.g. for a node and its sentinel,
+    /// returned in the order they were allocated.
+    ///
+    /// Panics if the pool's limit leaves fewer than two free indices.
+    pub fn allocate_pair(&mut self) -> (usize, usize) {
+        assert!(
+            self.limit - self.in_use >= 2,
+            "the pool has no room for the indices below its limit"
+        );
+        self.deferring_grow(|pool| {
+            let first = pool.new_id();
+            let second = pool.new_id();
+            (first, second)
+        })
+    }
+
+    /// Allocates a new index and passes it to `f`, e.g. to initialize the
+    /// slot it refers to, returning whatever `f` returns. If `f` panics
+    /// the index is given back to the pool, so it isn't leaked.
+    pub fn with_new_id<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(usize) -> R,
+    {
+        let old_max = self.next_id;
+        let id = self.new_id();
+        let guard = ReturnOnUnwind {
+            pool: self,
+            id,
+            old_max,
+        };
+        let result = f(id);
+        mem::forget(guard);
+        result
+    }
+
+    /// Allocates `n` indices, the same ones `n` calls to `new_id` would
+    /// hand out. When no indices need recycling they're claimed from the
+    /// tail in one step and returned as a single range, and any other
+    /// allocation which happens to be contiguous is reported that way
+    /// too, so callers can take a fast path for a single run.
+    ///
+    /// Panics if the pool's limit leaves fewer than `n` free indices.
+    pub fn new_ids_compact(&mut self, n: usize) -> NewIds {
+        assert!(
+            n <= self.limit - self.in_use,
+            "the pool has no room for the indices below its limit"
+        );
+        if n == 0 {
+            return NewIds::Scattered(Vec::new());
+        }
+
+        if self.in_use == self.next_id {
+            let range = Range {
+                min: self.next_id,
+                max: self.next_id + n - 1,
+            };
+            self.claim_range(range);
+            return NewIds::Contiguous(range);
+        }
+
+        let ids: Vec<usize> = self.deferring_grow(|pool| (0..n).map(|_| pool.new_id()).collect());
+        if ids.windows(2).all(|w| w[1] == w[0] + 1) {
+            NewIds::Contiguous(Range {
+                min: ids[0],
+                max: ids[n - 1],
+            })
+        } else {
+            NewIds::Scattered(ids)
+        }
+    }
+
+    /// Allocates `n` indices and returns them in ascending order. With
+    /// `prefer_contiguous`, they form a single run taken from the lowest
+    /// interior gap which can hold them, or from the tail otherwise, and
+    /// are only scattered over the lowest free indices when the pool's
+    /// limit leaves no room for a run. Without it, the `n` lowest free
+    /// indices are taken, like `n` calls to `new_id`.
+    ///
+    /// Panics if the pool's limit leaves fewer than `n` free indices.
+    pub fn allocate_n(&mut self, n: usize, prefer_contiguous: bool) -> Vec<usize> {
+        assert!(
+            n <= self.limit - self.in_use,
+            "the pool has no room for the indices below its limit"
+        );
+        if n == 0 {
+            return Vec::new();
+        }
+
+        if prefer_contiguous {
+            if let Some(range) = self.find_contiguous(n) {
+                self.claim_range(range);
+                return (range.min..range.max + 1).collect();
+            }
+        }
+        self.deferring_grow(|pool| (0..n).map(|_| pool.new_id()).collect())
+    }
+
+    /// Allocates `id` and runs `f`, e.g. to produce the value stored in the
+    /// slot it refers to, returning whatever `f` returns. If `f` panics
+    /// the index is given back to the pool and `maximum()` goes back to
+    /// what it was, even with auto-collapse off, so the reservation is
+    /// rolled back. Returns Err without running `f` if `id` is already in
+    /// use.
+    ///
+    /// Panics if `id` is not below the pool's limit (or `usize::MAX`).
+    pub fn with_request_id<R, F>(&mut self, id: usize, f: F) -> Result<R, AlreadyInUse>
+    where
+        F: FnOnce() -> R,
+    {
+        let old_max = self.next_id;
+        self.request_id(id)?;
+        let guard = ReturnOnUnwind {
+            pool: self,
+            id,
+            old_max,
+        };
+        let result = f();
+        mem::forget(guard);
+        Ok(result)
+    }
+
+    /// Like `new_id`, but returns an error instead of panicking when the
+    /// pool's limit has been reached.
+    #[inline]
+    pub fn try_new_id(&mut self) -> Result<usize, PoolFull> {
+        self.alloc_lowest()
+    }
+
+    /// Allocates the smallest index which is currently free. Unlike
+    /// `new_id`, which only promises uniqueness, this is guaranteed to
+    /// always hand out the lowest free index.
+    ///
+    /// Panics if the pool has a limit and every index below it is in use.
+    #[inline]
+    pub fn reserve_lowest(&mut self) -> usize {
+        match self.alloc_lowest() {
+            Ok(id) => id,
+            Err(_) => panic!("the pool has no free indices below its limit"),
+        }
+    }
+
+    /// Allocates the free index which `cmp` ranks first, where
+    /// `cmp(a, b) == Ordering::Less` means `a` is preferred over `b`. Only
+    /// recycled indices are considered; a fresh index from the tail is
+    /// handed out when there are none.
+    ///
+    /// This visits every free index below `maximum()`, so it costs
+    /// `O(maximum() - in_use())` comparisons rather than the `O(log n)` of
+    /// `new_id`.
+    ///
+    /// Panics if the pool has a limit and every index below it is in use.
+    pub fn new_id_by<F>(&mut self, cmp: F) -> usize
+    where
+        F: Fn(usize, usize) -> Ordering,
+    {
+        let best = self
+            .free_list
+            .free_ranges()
+            .flat_map(|range| range.min..range.max + 1)
+            .min_by(|&a, &b| cmp(a, b));
+
+        match best {
+            Some(id) => {
+                self.request_id(id).expect("free index was already in use");
+                id
+            }
+            None => self.reserve_lowest(),
+        }
+    }
+
+    /// Allocates the largest free index below the pool's limit. This is
+    /// the descending counterpart to `reserve_lowest`, e.g. for handing
+    /// out ids from the top of the space while `new_id` fills it from the
+    /// bottom. Returns `None` if the pool is full, or if it has no limit.
+    pub fn reserve_highest(&mut self) -> Option<usize> {
+        let id = match self.limit() {
+            Some(limit) if self.next_id < limit => limit - 1,
+            Some(_) => self.free_list.last()?,
+            None => return None,
+        };
+
+        self.request_id(id).expect("free index was already in use");
+        Some(id)
+    }
+
+    #[inline]
+    fn alloc_lowest(&mut self) -> Result<usize, PoolFull> {
+        // Nothing below `next_id` is free, so there's nothing to recycle
+        // and the free list doesn't need to be searched at all
+        let recycled = if self.free_list.free_ranges().len() == 0 {
+            None
+        } else {
+            self.free_list.set_first_used()
+        };
+
+        let id = match recycled {
+            Some(id) => {
+                self.recycled_allocations += 1;
+                id
+            }
+            None if self.next_id < self.limit => {
+                self.next_id += 1;
+                self.next_id - 1
+            }
+            None => return Err(PoolFull),
+        };
+
         self.in_use += 1;
+        self.on_allocated(Range::id(id));
+        Ok(id)
+    }
 
-        if let Some(id) = self.free_list.set_first_used() {
-            return id;
+    /// Like `request_id`, but returns an error instead of panicking when
+    /// `id` lies beyond the pool's limit.
+    #[inline]
+    pub fn checked_request_id(&mut self, id: usize) -> Result<(), RequestError> {
+        if id >= self.limit {
+            return Err(RequestError::OutOfBounds);
         }
 
-        let id = self.next_id;
-        self.next_id += 1;
-        return id;
+        self.request_id(id).map_err(|_| RequestError::AlreadyInUse)
     }
 
     #[inline]
     /// Attempts to allocate a specific index
+    ///
+    /// Panics if `id` is not below the pool's limit (or `usize::MAX`).
     pub fn request_id(&mut self, id: usize) -> Result<(), AlreadyInUse> {
-        assert!(id < usize::MAX);
+        assert!(
+            id < self.limit,
+            "requested an index beyond the pool's limit"
+        );
         if id == self.next_id {
             self.next_id += 1;
-            self.in_use += 1;
-            Ok(())
         } else if id > self.next_id {
             self.free_list.set_range_free(Range {
                 min: self.next_id,
                 max: id - 1,
             });
             self.next_id = id + 1;
-            self.in_use += 1;
+        } else if !self.free_list.set_used(id) {
+            return Err(AlreadyInUse);
+        }
+
+        self.in_use += 1;
+        self.on_allocated(Range::id(id));
+        Ok(())
+    }
+
+    /// Like `request_id`, but returns how many indices were freed into the
+    /// gap left behind when `id` lies past the end of the pool. Returns 0
+    /// when `id` is at or below the end, since no gap is created.
+    ///
+    /// Panics if `id` is not below the pool's limit (or `usize::MAX`).
+    #[inline]
+    pub fn request_id_tracked(&mut self, id: usize) -> Result<usize, AlreadyInUse> {
+        let gap = id.saturating_sub(self.next_id);
+        self.request_id(id)?;
+        Ok(gap)
+    }
+
+    /// Like `request_id`, but returns the indices which were freed into the
+    /// gap left behind when `id` lies past the end of the pool, e.g. to
+    /// initialize their slots. The iterator is empty when `id` is at or
+    /// below the end, since no gap is created.
+    ///
+    /// Panics if `id` is not below the pool's limit (or `usize::MAX`).
+    #[inline]
+    pub fn request_id_filling(
+        &mut self,
+        id: usize,
+    ) -> Result<impl Iterator<Item = usize>, AlreadyInUse> {
+        let gap_start = cmp::min(self.next_id, id);
+        self.request_id(id)?;
+        Ok(gap_start..id)
+    }
+
+    /// Allocates every index in `ids`, which may be in any order. Either
+    /// all of them are allocated, or Err is returned without changing
+    /// anything if any are already in use (or appear more than once).
+    /// Indices past the tail are claimed in a single pass, rather than
+    /// growing the pool one `request_id` at a time.
+    ///
+    /// Panics if any of `ids` is not below the pool's limit.
+    pub fn request_sparse(&mut self, ids: &[usize]) -> Result<(), AlreadyInUse> {
+        let mut sorted = ids.to_vec();
+        sorted.sort_unstable();
+
+        for (i, &id) in sorted.iter().enumerate() {
+            assert!(
+                id < self.limit,
+                "requested an index beyond the pool's limit"
+            );
+            if !self.is_free(id) || (i > 0 && sorted[i - 1] == id) {
+                return Err(AlreadyInUse);
+            }
+        }
+
+        let split = sorted.partition_point(|&id| id < self.next_id);
+        for &id in &sorted[..split] {
+            self.free_list.set_used(id);
+        }
+
+        let mut tail = self.next_id;
+        for &id in &sorted[split..] {
+            if id > tail {
+                self.free_list.set_range_free(Range {
+                    min: tail,
+                    max: id - 1,
+                });
+            }
+            tail = id + 1;
+        }
+        self.next_id = tail;
+
+        self.in_use += sorted.len();
+        for &id in &sorted {
+            self.on_allocated(Range::id(id));
+        }
+        Ok(())
+    }
+
+    /// Like `request_sparse`, but `sorted_ids` must already be strictly
+    /// ascending, which lets the new free ranges be built in a single
+    /// merge pass over the old ones instead of probing the free list once
+    /// per index. Meant for reserving a large, known-good set of indices,
+    /// e.g. when warm-starting. Either all of them are allocated, or Err
+    /// is returned without changing anything if any are already in use.
+    ///
+    /// Panics if any of `sorted_ids` is not below the pool's limit.
+    pub fn request_sorted(&mut self, sorted_ids: &[usize]) -> Result<(), AlreadyInUse> {
+        debug_assert!(
+            sorted_ids.windows(2).all(|w| w[0] < w[1]),
+            "ids must be strictly ascending"
+        );
+        let last = match sorted_ids.last() {
+            Some(&last) => last,
+            None => return Ok(()),
+        };
+        assert!(
+            last < self.limit,
+            "requested an index beyond the pool's limit"
+        );
 
-            Ok(())
-        } else if self.free_list.set_used(id) {
-            self.in_use += 1;
-            Ok(())
+        let tail = if last >= self.next_id {
+            Some(Range {
+                min: self.next_id,
+                max: last,
+            })
         } else {
-            Err(AlreadyInUse)
+            None
+        };
+
+        // Every id must land inside a free range (or the tail up to the
+        // last id), splitting it around the id
+        let mut free = Vec::with_capacity(self.free_ranges_count() + 1);
+        let mut ids = sorted_ids.iter().cloned().peekable();
+        for range in self.free_list.free_ranges().cloned().chain(tail) {
+            let mut start = range.min;
+            while let Some(&id) = ids.peek() {
+                if id > range.max {
+                    break;
+                }
+                if id < start {
+                    return Err(AlreadyInUse);
+                }
+                if id > start {
+                    free.push(Range {
+                        min: start,
+                        max: id - 1,
+                    });
+                }
+                start = id + 1;
+                ids.next();
+            }
+            if start <= range.max {
+                free.push(Range {
+                    min: start,
+                    max: range.max,
+                });
+            }
+        }
+        if ids.next().is_some() {
+            return Err(AlreadyInUse);
+        }
+
+        self.free_list.clear();
+        for range in free {
+            self.free_list.set_range_free(range);
+        }
+        self.next_id = cmp::max(self.next_id, last + 1);
+        self.in_use += sorted_ids.len();
+
+        let mut run_start = 0;
+        for i in 1..sorted_ids.len() + 1 {
+            if i == sorted_ids.len() || sorted_ids[i] != sorted_ids[i - 1] + 1 {
+                self.on_allocated(Range {
+                    min: sorted_ids[run_start],
+                    max: sorted_ids[i - 1],
+                });
+                run_start = i;
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocates `id` if it is free. Returns true if it was reserved, or
+    /// false if it was already in use or lies beyond the pool's limit.
+    #[inline]
+    pub fn request_if_free(&mut self, id: usize) -> bool {
+        self.checked_request_id(id).is_ok()
+    }
+
+    /// Allocates `id` if it is free. Otherwise returns the free index
+    /// closest to `id` as the error, without reserving it, so the caller
+    /// can propose it instead. Ties go to the lower index.
+    ///
+    /// Panics if `id` is not below the pool's limit (or `usize::MAX`), or
+    /// if `id` is in use and every other index below the limit is too.
+    pub fn request_id_or_nearest(&mut self, id: usize) -> Result<(), usize> {
+        if self.request_id(id).is_ok() {
+            return Ok(());
+        }
+
+        // `id` is in use, so it lies below `next_id` and inside no free range
+        let above = self
+            .free_list
+            .free_ranges_after(id)
+            .next()
+            .map(|range| range.min)
+            .or(Some(self.next_id).filter(|&tail| tail < self.limit));
+        let below = self
+            .free_list
+            .free_ranges()
+            .rev()
+            .find(|range| range.max < id)
+            .map(|range| range.max);
+
+        let nearest = match (below, above) {
+            (Some(below), Some(above)) if id - below <= above - id => below,
+            (_, Some(above)) => above,
+            (Some(below), None) => below,
+            (None, None) => panic!("the pool has no free indices below its limit"),
+        };
+        Err(nearest)
+    }
+
+    /// Allocates an index for each entry of `preferred`, in order, and
+    /// returns the index each one ended up with. An entry keeps its
+    /// preferred index if that is free, and otherwise gets whatever
+    /// `new_id` hands out next, so conflicts (including repeats within
+    /// `preferred`) are resolved by first come, first served.
+    ///
+    /// Panics if the pool's limit is reached.
+    pub fn reserve_specific_or_next(&mut self, preferred: &[usize]) -> Vec<usize> {
+        self.deferring_grow(|pool| {
+            preferred
+                .iter()
+                .map(|&id| {
+                    if pool.request_if_free(id) {
+                        id
+                    } else {
+                        pool.new_id()
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Calls `gen` until it produces an index which is free, then
+    /// allocates and returns it, e.g. for ids derived from hashes where
+    /// collisions are retried.
+    ///
+    /// This loops forever if `gen` never produces a free index, e.g. when
+    /// a bounded pool is full. Use `try_reserve_from` to give up after a
+    /// number of attempts instead.
+    pub fn reserve_from<F>(&mut self, mut gen: F) -> usize
+    where
+        F: FnMut() -> usize,
+    {
+        loop {
+            let id = gen();
+            if self.request_if_free(id) {
+                return id;
+            }
         }
     }
 
+    /// Like `reserve_from`, but calls `gen` at most `max_attempts` times,
+    /// returning `None` if none of the indices it produced were free.
+    pub fn try_reserve_from<F>(&mut self, max_attempts: usize, mut gen: F) -> Option<usize>
+    where
+        F: FnMut() -> usize,
+    {
+        (0..max_attempts)
+            .map(|_| gen())
+            .find(|&id| self.request_if_free(id))
+    }
+
     /// Gives an Id back to the pool so that it may be handed out again.
     /// Returns Err if the Id was not in use at the time. Whether ignoring
-    /// such an error is okay is up to your own usecase.
+    /// such an error is okay is up to your own usecase. Never panics:
+    /// indices past the end, or which were already returned, are errors.
     #[inline]
     pub fn return_id(&mut self, id: usize) -> Result<(), AlreadyReturned> {
         if id >= self.next_id {
             return Err(AlreadyReturned);
         }
 
-        if id + 1 == self.next_id {
+        // The highest index may already sit in a free range when the tail
+        // isn't being collapsed, or after `shrink_to` kept some of it
+        if id + 1 == self.next_id && self.collapsing() && self.free_list.last() != Some(id) {
             self.next_id -= 1;
+            self.grown_to = self.next_id;
         } else {
             if !self.free_list.set_free(id) {
                 return Err(AlreadyReturned);
             }
-            assert!(self.free_list.is_free(id));
+            // Only a broken `FreeListBackend` could fail this
+            debug_assert!(self.free_list.is_free(id));
         }
 
         self.in_use -= 1;
+        self.on_freed(Range::id(id));
 
-        while self.collapse_next() {}
+        if self.collapsing() {
+            while self.collapse_next() {}
+        }
+        self.check_fragmentation();
+
+        Ok(())
+    }
+
+    /// Frees the smallest index which is in use and returns it, or `None`
+    /// if the pool is empty. Calling this repeatedly drains the pool in
+    /// ascending order, like dequeuing tasks by id.
+    pub fn pop_lowest_used(&mut self) -> Option<usize> {
+        let id = self.all_indices().next()?;
+        self.return_id(id).expect("in-use index was already free");
+        Some(id)
+    }
+
+    /// Frees the largest index which is in use and returns it, or `None`
+    /// if the pool is empty. This is the stack-like counterpart to
+    /// `pop_lowest_used`; with auto-collapse on, `maximum()` shrinks
+    /// below the popped index each time.
+    pub fn pop_highest_used(&mut self) -> Option<usize> {
+        if self.in_use == 0 {
+            return None;
+        }
+
+        // The top of the pool may be free if the tail hasn't been collapsed
+        let id = match self.free_list.last() {
+            Some(last) if last + 1 == self.next_id => {
+                let top_free = self.free_list.free_ranges().next_back()?;
+                top_free.min - 1
+            }
+            _ => self.next_id - 1,
+        };
+        self.return_id(id).expect("in-use index was already free");
+        Some(id)
+    }
+
+    /// Moves an allocation from `from` to `to`, e.g. when defragmenting by
+    /// moving a live entity into a lower hole: `to` becomes in use and
+    /// `from` becomes free. Nothing changes if `from` isn't in use, or
+    /// `to` isn't free or lies beyond the pool's limit.
+    pub fn relocate(&mut self, from: usize, to: usize) -> Result<(), RelocateError> {
+        if self.is_free(from) {
+            return Err(RelocateError::NotInUse);
+        }
+        if to >= self.limit {
+            return Err(RelocateError::OutOfBounds);
+        }
+        if !self.is_free(to) {
+            return Err(RelocateError::AlreadyInUse);
+        }
 
+        self.request_id(to).expect("free index was already in use");
+        self.return_id(from).expect("used index was already free");
         Ok(())
     }
 
+    /// Returns `id` to the pool if it is in use. Returns true if it was
+    /// freed, or false if it was already free, for cleanup code which
+    /// doesn't care about the difference.
+    #[inline]
+    pub fn return_if_used(&mut self, id: usize) -> bool {
+        self.return_id(id).is_ok()
+    }
+
+    /// Frees every in-use index at or above `threshold` and returns them
+    /// in ascending order, so that any data associated with them can be
+    /// cleaned up. Afterwards `maximum()` is at most `threshold`. With
+    /// auto-collapse off, or inside a `batch`, the freed tail is still
+    /// folded back in, but only down to `threshold`.
+    pub fn return_all_above(&mut self, threshold: usize) -> Vec<usize> {
+        let runs: Vec<Range> = self
+            .used_ranges()
+            .filter(|run| run.max >= threshold)
+            .map(|run| Range {
+                min: cmp::max(run.min, threshold),
+                max: run.max,
+            })
+            .collect();
+
+        let mut freed = Vec::new();
+        for &run in &runs {
+            freed.extend(run.min..run.max + 1);
+        }
+        for &run in runs.iter().rev() {
+            self.release_range(run);
+        }
+        self.shrink_to(threshold);
+        freed
+    }
+
     /// Returns an upper bound on the number of IDs which have been
     /// allocated, specifically the `highest numbered ID in use + 1`.
     /// Useful if you're going to e.g. create a Vec which has room
@@ -144,84 +907,1851 @@ impl IndexPool {
         self.next_id
     }
 
+    /// Returns how far the pool's backing storage has been sized: the
+    /// larger of `maximum()` and the value last passed to `reserve`.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        cmp::max(self.reserved, self.next_id)
+    }
+
+    /// Raises `capacity()` to at least `capacity` (clamped to the pool's
+    /// limit), e.g. after sizing a buffer generously. This doesn't allocate
+    /// anything or move `maximum()`; the indices in
+    /// `[maximum()..capacity())` are free like any other index past the
+    /// tail, so allocations still come from the bottom of the pool.
+    /// `clear` leaves the capacity as it is.
+    #[inline]
+    pub fn reserve(&mut self, capacity: usize) {
+        self.reserved = cmp::max(self.reserved, cmp::min(capacity, self.limit));
+    }
+
+    /// Grows `data` to `maximum()` elements, filling any new slots with
+    /// `default`, so that every allocated index can be used to index it.
+    /// Does nothing if `data` is already large enough.
+    ///
+    /// ```
+    /// extern crate index_pool;
+    /// use index_pool::IndexPool;
+    ///
+    /// fn main() {
+    ///     let mut pool = IndexPool::new();
+    ///     let mut names = Vec::new();
+    ///
+    ///     let id = pool.new_id();
+    ///     pool.ensure_capacity(&mut names, "");
+    ///     names[id] = "alice";
+    ///
+    ///     assert_eq!(names, vec!["alice"]);
+    /// }
+    /// ```
+    #[inline]
+    pub fn ensure_capacity<T: Clone>(&self, data: &mut Vec<T>, default: T) {
+        if data.len() < self.next_id {
+            data.resize(self.next_id, default);
+        }
+    }
+
     /// Returns the number of currently in-use indices
     #[inline]
     pub fn in_use(&self) -> usize {
         self.in_use
     }
 
+    /// Checks whether the in-use indices are exactly `[0..in_use())`, with
+    /// no gaps below `maximum()`. This only compares two counters, so it's
+    /// a cheap guard for fast paths which don't handle gaps.
+    #[inline]
+    pub fn is_contiguous(&self) -> bool {
+        self.in_use == self.next_id
+    }
+
+    /// Returns the length of the `[0..index)` range which was reserved by
+    /// `with_initial_index`, or `0` for other pools.
+    #[inline]
+    pub fn prefix_len(&self) -> usize {
+        self.prefix
+    }
+
+    /// Returns the number of in-use indices outside of the `[0..index)`
+    /// prefix reserved by `with_initial_index`, i.e. the ones you actually
+    /// allocated. Indices inside the prefix are never counted, even if
+    /// they were returned and then handed out again.
+    pub fn in_use_excluding_prefix(&self) -> usize {
+        let prefix_free: usize = self
+            .free_list
+            .free_ranges()
+            .take_while(|range| range.min < self.prefix)
+            .map(|range| cmp::min(range.max, self.prefix - 1) - range.min + 1)
+            .sum();
+        let prefix_used = cmp::min(self.prefix, self.next_id) - prefix_free;
+        self.in_use - prefix_used
+    }
+
     #[inline]
     /// Checks if a specific index is currently free
     pub fn is_free(&self, id: usize) -> bool {
         id >= self.next_id || self.free_list.is_free(id)
     }
 
-    /// Returns an iterator over all indices which are in use
-    #[inline]
-    pub fn all_indices(&self) -> iter::IndexIter {
-        iter::IndexIter::new(self.free_list.free_ranges(), self.next_id)
+    /// Checks that the pool agrees with storage indexed by it, e.g. a
+    /// `Vec<Option<T>>`: every slot holding `Some` must be in use, every
+    /// slot holding `None` must be free, and no index past the end of
+    /// `data` may be in use. Stops at the first mismatch. This is the
+    /// check to go with `from_option_slice`, for catching desyncs in tests
+    /// and debug builds.
+    pub fn matches_option_slice<T>(&self, data: &[Option<T>]) -> bool {
+        data.iter()
+            .enumerate()
+            .all(|(id, slot)| slot.is_some() != self.is_free(id))
+            && self.all_indices_after(data.len()).next().is_none()
     }
 
+    /// Checks whether every index in `ids` is currently in use, stopping
+    /// at the first free one. Returns true if `ids` is empty.
     #[inline]
-    pub fn all_indices_after(&self, after: usize) -> iter::IndexAfterIter {
-        iter::IndexAfterIter::new(self.free_list.free_ranges_after(after), after, self.next_id)
+    pub fn contains_all<I>(&self, ids: I) -> bool
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        ids.into_iter().all(|id| !self.is_free(id))
     }
 
-    #[inline]
-    fn collapse_next(&mut self) -> bool {
-        if let Some(last_range) = self.free_list.free_ranges().rev().nth(0).cloned() {
-            if last_range.max + 1 == self.next_id {
-                self.free_list.remove_last_contiguous();
-                self.next_id = last_range.min;
-                return true;
+    /// Checks whether every index in `range` (inclusive) is currently
+    /// free, looking up the single free range that would have to contain
+    /// it rather than checking each index. Indices past the end of the
+    /// pool are free, but those at or beyond its limit are not.
+    pub fn is_free_range(&self, range: Range) -> bool {
+        if range.min >= self.next_id {
+            return range.max < self.limit;
+        }
+
+        let below_tail = cmp::min(range.max, self.next_id - 1);
+        let contained = self
+            .free_list
+            .free_ranges_after(range.min)
+            .next()
+            .map_or(false, |free| {
+                free.min <= range.min && free.max >= below_tail
+            });
+        contained && range.max < self.limit
+    }
+
+    /// Returns the occupancy of 64 indices at once: bit `k` is set if
+    /// index `word_index * 64 + k` is in use. Indices at or above
+    /// `maximum()` read as free. Only the free ranges which intersect the
+    /// word are visited.
+    pub fn as_used_mask_u64(&self, word_index: usize) -> u64 {
+        let base = match word_index.checked_mul(64) {
+            Some(base) if base < self.next_id => base,
+            _ => return 0,
+        };
+        let end = cmp::min(base.saturating_add(64), self.next_id);
+
+        // Bits for `[from..to)` relative to `base`
+        let bits = |from: usize, to: usize| {
+            let len = to - from;
+            let ones = if len == 64 { !0 } else { (1u64 << len) - 1 };
+            ones << (from - base)
+        };
+
+        let mut mask = bits(base, end);
+        for free in self.free_list.free_ranges_after(base) {
+            if free.min >= end {
+                break;
             }
+            mask &= !bits(cmp::max(free.min, base), cmp::min(free.max + 1, end));
+        }
+        mask
+    }
+
+    /// Checks whether any index in `range` (inclusive) is currently in
+    /// use, e.g. before claiming a range handed out by another allocator.
+    /// Unlike `!is_free_range(range)`, indices beyond the pool's limit
+    /// don't count, since they can never be in use.
+    pub fn overlaps(&self, range: Range) -> bool {
+        if range.min >= self.next_id {
+            return false;
         }
 
-        false
+        let below_tail = cmp::min(range.max, self.next_id - 1);
+        !self
+            .free_list
+            .free_ranges_after(range.min)
+            .next()
+            .map_or(false, |free| {
+                free.min <= range.min && free.max >= below_tail
+            })
     }
 
-    #[inline]
-    pub fn clear(&mut self) {
-        self.free_list.clear();
-        self.in_use = 0;
-        self.next_id = 0;
+    /// Returns the `n` lowest free indices, in the order `new_id` would
+    /// hand them out, without actually allocating any of them. Recycled
+    /// holes come first, followed by fresh indices from the tail. Fewer
+    /// than `n` are returned if the pool's limit would be reached.
+    pub fn first_n_free(&self, n: usize) -> Vec<usize> {
+        let mut free = Vec::with_capacity(n);
+        for range in self.free_list.free_ranges() {
+            for id in range.min..range.max + 1 {
+                if free.len() == n {
+                    return free;
+                }
+                free.push(id);
+            }
+        }
+
+        let remaining = n - free.len();
+        let end = cmp::min(self.next_id.saturating_add(remaining), self.limit);
+        free.extend(self.next_id..end);
+        free
     }
-}
 
-impl Default for IndexPool {
-    /// Constructs an empty IndexPool. Indices will start at `0`.
-    #[inline]
-    fn default() -> Self {
-        IndexPool::new()
+    /// Gathers the pool's metrics in a single pass over the free ranges.
+    pub fn stats(&self) -> PoolStats {
+        let mut free_count = 0;
+        let mut free_ranges_count = 0;
+        let mut largest_free_gap = 0;
+        for range in self.free_list.free_ranges() {
+            let len = range.max - range.min + 1;
+            free_count += len;
+            free_ranges_count += 1;
+            largest_free_gap = cmp::max(largest_free_gap, len);
+        }
+
+        let fragmentation = if free_count == 0 {
+            0.0
+        } else {
+            1.0 - largest_free_gap as f64 / free_count as f64
+        };
+
+        PoolStats {
+            in_use: self.in_use,
+            maximum: self.next_id,
+            free_count,
+            free_ranges_count,
+            largest_free_gap,
+            fragmentation,
+        }
     }
-}
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct AlreadyReturned;
+    /// Checks whether `other` has exactly the same `maximum()` and free
+    /// indices as this pool, however either one got there. Counters,
+    /// limits, statistics and callbacks aren't compared.
+    pub fn same_free_set<C: FreeListBackend>(&self, other: &IndexPool<C>) -> bool {
+        self.next_id == other.next_id
+            && self.canonical_free_ranges() == other.canonical_free_ranges()
+    }
 
-impl fmt::Display for AlreadyReturned {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_str(self.description())
+    /// The free ranges as `(min, max)` pairs, with any touching ranges
+    /// merged so that equal free sets always compare equal
+    fn canonical_free_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for free in self.free_list.free_ranges() {
+            match ranges.last_mut() {
+                Some(last) if last.1 + 1 >= free.min => {
+                    last.1 = cmp::max(last.1, free.max);
+                }
+                _ => ranges.push((free.min, free.max)),
+            }
+        }
+        ranges
     }
-}
 
-impl Error for AlreadyReturned {
-    fn description(&self) -> &str {
-        "An index was tried to be returned to the pool, but it was already marked as free."
+    /// Returns the indices which are in use in this pool but not in
+    /// `other`, in ascending order. Both pools' runs of in-use indices are
+    /// walked in tandem, so the work done is proportional to the number
+    /// of runs rather than the number of indices.
+    pub fn indices_difference<C: FreeListBackend>(
+        &self,
+        other: &IndexPool<C>,
+    ) -> impl Iterator<Item = usize> {
+        self.combine_used(other, |a, b| a && !b)
+            .into_iter()
+            .flat_map(|run| run.min..run.max + 1)
     }
-}
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct AlreadyInUse;
+    /// Returns the indices which are in use in both this pool and
+    /// `other`, in ascending order. See `indices_difference`.
+    pub fn indices_intersection<C: FreeListBackend>(
+        &self,
+        other: &IndexPool<C>,
+    ) -> impl Iterator<Item = usize> {
+        self.combine_used(other, |a, b| a && b)
+            .into_iter()
+            .flat_map(|run| run.min..run.max + 1)
+    }
 
-impl fmt::Display for AlreadyInUse {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_str(self.description())
+    /// Returns the indices which are in use in exactly one of this pool
+    /// and `other`, in ascending order, e.g. everything that changed
+    /// between two snapshots. See `indices_difference`.
+    pub fn indices_symmetric_difference<C: FreeListBackend>(
+        &self,
+        other: &IndexPool<C>,
+    ) -> impl Iterator<Item = usize> {
+        self.combine_used(other, |a, b| a != b)
+            .into_iter()
+            .flat_map(|run| run.min..run.max + 1)
     }
-}
 
-impl Error for AlreadyInUse {
-    fn description(&self) -> &str {
-        "An index was requested which was already marked as in use."
+    /// Walks the in-use runs of both pools together and returns the runs
+    /// of indices for which `keep(in_self, in_other)` is true
+    fn combine_used<C, F>(&self, other: &IndexPool<C>, keep: F) -> Vec<Range>
+    where
+        C: FreeListBackend,
+        F: Fn(bool, bool) -> bool,
+    {
+        let a = self.used_ranges_vec();
+        let b = other.used_ranges_vec();
+        let mut runs: Vec<Range> = Vec::new();
+        let (mut i, mut j, mut pos) = (0, 0, 0);
+        loop {
+            while i < a.len() && a[i].max < pos {
+                i += 1;
+            }
+            while j < b.len() && b[j].max < pos {
+                j += 1;
+            }
+
+            // The next point where either pool switches between used and free
+            let boundary = |runs: &[Range], k: usize| match runs.get(k) {
+                Some(run) if run.min <= pos => (true, Some(run.max + 1)),
+                Some(run) => (false, Some(run.min)),
+                None => (false, None),
+            };
+            let (in_a, end_a) = boundary(&a, i);
+            let (in_b, end_b) = boundary(&b, j);
+            let end = match (end_a, end_b) {
+                (Some(x), Some(y)) => cmp::min(x, y),
+                (Some(x), None) | (None, Some(x)) => x,
+                (None, None) => break,
+            };
+
+            if (in_a || in_b) && keep(in_a, in_b) {
+                match runs.last_mut() {
+                    Some(last) if last.max + 1 == pos => last.max = end - 1,
+                    _ => runs.push(Range {
+                        min: pos,
+                        max: end - 1,
+                    }),
+                }
+            }
+            pos = end;
+        }
+        runs
+    }
+
+    /// Returns a new pool whose in-use indices are exactly this pool's
+    /// in-use indices within `[lo..hi)`, leaving this pool untouched, e.g.
+    /// to hand a snapshot of one window to a worker thread. The indices
+    /// aren't rebased, and the new pool keeps this pool's limit but none
+    /// of its callbacks or statistics.
+    ///
+    /// Panics if `lo > hi`.
+    pub fn clone_windowed(&self, lo: usize, hi: usize) -> Self {
+        assert!(lo <= hi, "the window's start is past its end");
+        let mut pool = Self::empty(0);
+        pool.limit = self.limit;
+        pool.auto_collapse = self.auto_collapse;
+        if lo == hi {
+            return pool;
+        }
+
+        let mut next = 0;
+        for run in self.used_ranges() {
+            if run.max < lo {
+                continue;
+            }
+            if run.min >= hi {
+                break;
+            }
+
+            let run = Range {
+                min: cmp::max(run.min, lo),
+                max: cmp::min(run.max, hi - 1),
+            };
+            if run.min > next {
+                pool.free_list.set_range_free(Range {
+                    min: next,
+                    max: run.min - 1,
+                });
+            }
+            pool.in_use += run.max - run.min + 1;
+            next = run.max + 1;
+        }
+        pool.next_id = next;
+        pool
+    }
+
+    /// Returns the highest `in_use()` seen since the pool was constructed
+    /// or `reset_statistics` was last called.
+    #[inline]
+    pub fn peak_in_use(&self) -> usize {
+        cmp::max(self.peak_in_use, self.in_use)
+    }
+
+    /// Returns the highest `maximum()` seen since the pool was constructed
+    /// or `reset_statistics` was last called.
+    #[inline]
+    pub fn peak_maximum(&self) -> usize {
+        cmp::max(self.peak_maximum, self.next_id)
+    }
+
+    /// Returns how many indices have been allocated since the pool was
+    /// constructed or `reset_statistics` was last called. Together with
+    /// `total_frees` this is useful for leak detection: the difference
+    /// between the two is how much `in_use()` has grown over that time.
+    #[inline]
+    pub fn total_allocations(&self) -> u64 {
+        self.total_allocations
+    }
+
+    /// Returns how many indices have been freed since the pool was
+    /// constructed or `reset_statistics` was last called.
+    #[inline]
+    pub fn total_frees(&self) -> u64 {
+        self.total_frees
+    }
+
+    /// Zeroes the allocation and free totals, and resets the peaks to the
+    /// pool's current `in_use()` and `maximum()`.
+    #[inline]
+    pub fn reset_statistics(&mut self) {
+        self.peak_in_use = self.in_use;
+        self.peak_maximum = self.next_id;
+        self.total_allocations = 0;
+        self.total_frees = 0;
+        self.recycled_allocations = 0;
+    }
+
+    /// Returns the fraction of allocations since the pool was constructed
+    /// or `reset_statistics` was last called which `new_id` served by
+    /// recycling a freed index rather than growing the pool. A low ratio
+    /// means the tail is churning; `0.0` if nothing has been allocated.
+    pub fn reuse_ratio(&self) -> f64 {
+        if self.total_allocations == 0 {
+            0.0
+        } else {
+            self.recycled_allocations as f64 / self.total_allocations as f64
+        }
+    }
+
+    /// Consumes the pool and hands back `maximum()` along with the free
+    /// ranges below it, moving them out rather than cloning them.
+    #[inline]
+    pub fn into_free_ranges(self) -> (usize, B) {
+        (self.next_id, self.free_list)
+    }
+
+    /// Appends the free ranges to `buf` as inclusive `(min, max)` pairs in
+    /// ascending order, e.g. to copy them into shared memory. Pass them to
+    /// `read_ranges_from` along with `maximum()` to rebuild the pool.
+    pub fn write_ranges_to(&self, buf: &mut Vec<(usize, usize)>) {
+        buf.reserve(self.free_ranges_count());
+        buf.extend(
+            self.free_list
+                .free_ranges()
+                .map(|free| (free.min, free.max)),
+        );
+    }
+
+    /// Returns the number of contiguous free ranges the pool is tracking.
+    ///
+    /// This is the best available measure of the free list's memory use:
+    /// the backend doesn't expose its capacity, but each range costs at
+    /// least `size_of::<Range>()` bytes plus whatever overhead the backend
+    /// adds per range, so the footprint grows linearly with this count. A
+    /// steadily climbing count is a sign of fragmentation.
+    #[inline]
+    pub fn free_ranges_count(&self) -> usize {
+        self.free_list.free_ranges().len()
+    }
+
+    /// Returns an iterator over all indices which are in use
+    #[inline]
+    pub fn all_indices(&self) -> iter::IndexIter<'_, B> {
+        iter::IndexIter::new(self.free_list.free_ranges(), self.next_id)
+    }
+
+    /// Returns an iterator over the in-use indices below `upper`, e.g. the
+    /// length of a fixed-size arena, even when `maximum()` lies past it.
+    #[inline]
+    pub fn all_indices_bounded(&self, upper: usize) -> iter::IndexIter<'_, B> {
+        iter::IndexIter::new(self.free_list.free_ranges(), cmp::min(upper, self.next_id))
+    }
+
+    /// Describes the pool's layout as a compact string of used and free
+    /// runs, followed by the open tail, e.g.
+    /// `[0-4 used][5-6 free][7-10 used][11+ free tail]`. Much easier to
+    /// read in assertion messages than the `Debug` output.
+    pub fn debug_layout(&self) -> String {
+        use std::fmt::Write;
+
+        fn run(out: &mut String, min: usize, max: usize, kind: &str) {
+            if min == max {
+                let _ = write!(out, "[{} {}]", min, kind);
+            } else {
+                let _ = write!(out, "[{}-{} {}]", min, max, kind);
+            }
+        }
+
+        let mut out = String::new();
+        let mut next = 0;
+        for free in self.free_list.free_ranges() {
+            if free.min > next {
+                run(&mut out, next, free.min - 1, "used");
+            }
+            run(&mut out, free.min, free.max, "free");
+            next = free.max + 1;
+        }
+        if self.next_id > next {
+            run(&mut out, next, self.next_id - 1, "used");
+        }
+
+        if self.limit == usize::MAX {
+            let _ = write!(out, "[{}+ free tail]", self.next_id);
+        } else if self.next_id < self.limit {
+            run(&mut out, self.next_id, self.limit - 1, "free tail");
+        }
+        out
+    }
+
+    /// Returns an iterator over the contiguous runs of in-use indices,
+    /// in ascending order
+    #[inline]
+    pub fn used_ranges(&self) -> iter::UsedRangeIter<'_, B> {
+        iter::UsedRangeIter::new(self.free_list.free_ranges(), self.next_id)
+    }
+
+    /// Takes a snapshot of every index which is in use, sorted in
+    /// ascending order, e.g. for a crash dump. Equivalent to
+    /// `all_indices().collect()`, but the Vec is allocated with exactly
+    /// `in_use()` capacity up front.
+    pub fn ids_in_use(&self) -> Vec<usize> {
+        let mut ids = Vec::with_capacity(self.in_use);
+        ids.extend(self.all_indices());
+        ids
+    }
+
+    /// Collects the contiguous runs of in-use indices into a Vec, in
+    /// ascending order. Equivalent to `used_ranges().collect()`, but builds
+    /// the list in one pass with its capacity reserved up front.
+    pub fn used_ranges_vec(&self) -> Vec<Range> {
+        // Every free range can split off at most one run before it
+        let mut runs = Vec::with_capacity(self.free_ranges_count() + 1);
+        let mut start = 0;
+        for free in self.free_list.free_ranges() {
+            if free.min >= self.next_id {
+                break;
+            }
+            if free.min > start {
+                runs.push(Range {
+                    min: start,
+                    max: free.min - 1,
+                });
+            }
+            start = free.max + 1;
+        }
+        if self.next_id > start {
+            runs.push(Range {
+                min: start,
+                max: self.next_id - 1,
+            });
+        }
+        runs
+    }
+
+    /// Calls `f` with every contiguous run of in-use indices, and returns
+    /// the whole run to the pool when `f` returns false. Much cheaper than
+    /// freeing index by index when liveness is tracked in runs.
+    pub fn retain_ranges<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Range) -> bool,
+    {
+        let runs: Vec<Range> = self.used_ranges().collect();
+        for run in runs {
+            if !f(run) {
+                self.release_range(run);
+            }
+        }
+    }
+
+    /// Allocates `n` contiguous indices, reusing the lowest interior gap
+    /// which can hold them or extending the tail otherwise. The returned
+    /// handle remembers exactly which indices were allocated, and must be
+    /// passed to `free_block` to give them back.
+    ///
+    /// Panics if `n` is `0`, or if the pool's limit leaves no room.
+    pub fn allocate_block(&mut self, n: usize) -> BlockId {
+        BlockId {
+            range: self.reserve_range_or_grow(n),
+        }
+    }
+
+    /// Allocates `n` contiguous indices and returns them. The lowest
+    /// interior gap which can hold them is reused if there is one,
+    /// otherwise the tail is extended, so this always succeeds for a pool
+    /// without a limit.
+    ///
+    /// Panics if `n` is `0`, or if the pool's limit leaves no room.
+    pub fn reserve_range_or_grow(&mut self, n: usize) -> Range {
+        assert!(n > 0, "cannot allocate an empty range");
+        let range = self
+            .find_contiguous(n)
+            .expect("the pool has no room for the range below its limit");
+        self.claim_range(range);
+        range
+    }
+
+    /// Allocates exactly `n` brand-new indices from the tail, starting at
+    /// `maximum()`, and returns them. Unlike `reserve_range_or_grow`,
+    /// interior gaps are never reused, so every returned index lies above
+    /// everything allocated so far.
+    ///
+    /// Panics if `n` is `0`, or if the pool's limit leaves no room.
+    pub fn reserve_tail(&mut self, n: usize) -> Range {
+        assert!(n > 0, "cannot allocate an empty range");
+        let range = self
+            .next_id
+            .checked_add(n - 1)
+            .filter(|&max| max < self.limit)
+            .map(|max| Range {
+                min: self.next_id,
+                max,
+            })
+            .expect("the pool has no room for the range below its limit");
+        self.claim_range(range);
+        range
+    }
+
+    /// Allocates `count` disjoint blocks of `block_size` contiguous indices
+    /// and returns their starts, e.g. to carve out per-worker arenas at
+    /// startup. Each block reuses the lowest interior gap which can hold
+    /// it, or extends the tail otherwise. The blocks aren't necessarily
+    /// next to each other.
+    ///
+    /// Panics if `block_size` is `0`, or if the pool's limit leaves no
+    /// room for all of the blocks, in which case none are allocated.
+    pub fn allocate_blocks(&mut self, block_size: usize, count: usize) -> Vec<usize> {
+        assert!(block_size > 0, "cannot allocate an empty range");
+
+        // Place every block in a scratch copy of the free list first, so a
+        // failure leaves no trace on the pool, its statistics or callbacks
+        let mut scratch = Self::empty(0);
+        scratch.free_list = self.free_list.clone();
+        scratch.next_id = self.next_id;
+        scratch.limit = self.limit;
+        let mut blocks = Vec::with_capacity(count);
+        for _ in 0..count {
+            let range = scratch
+                .find_contiguous(block_size)
+                .expect("the pool has no room for the blocks below its limit");
+            scratch.claim_range(range);
+            blocks.push(range);
+        }
+
+        for &range in &blocks {
+            self.claim_range(range);
+        }
+        blocks.iter().map(|range| range.min).collect()
+    }
+
+    /// Allocates `n` contiguous indices like `allocate_block`, but returns
+    /// a lease which frees them when dropped, so the block can't be leaked
+    /// by an early return or a panic.
+    ///
+    /// Panics if `n` is `0`, or if the pool's limit leaves no room.
+    pub fn lease_contiguous(&mut self, n: usize) -> lease::BlockLease<'_, B> {
+        let range = self.reserve_range_or_grow(n);
+        lease::BlockLease::new(self, range)
+    }
+
+    /// Frees every index which was allocated for `block`. Returns Err if
+    /// any of them are no longer in use, e.g. because the block came from
+    /// a different pool.
+    pub fn free_block(&mut self, block: BlockId) -> Result<(), AlreadyReturned> {
+        if !self.is_range_used(block.range) {
+            return Err(AlreadyReturned);
+        }
+
+        self.release_range(block.range);
+        Ok(())
+    }
+
+    /// Allocates a block of `block_size` contiguous indices starting at a
+    /// multiple of `block_size`, and returns that start, e.g. to keep
+    /// structure-of-arrays storage aligned to whole blocks. Freed block
+    /// slots are reused first, otherwise the tail is extended. Give the
+    /// block back with `free_block_slot`.
+    ///
+    /// Panics if `block_size` is `0`, or if the pool's limit leaves no
+    /// room.
+    pub fn allocate_block_slot(&mut self, block_size: usize) -> usize {
+        let range = self
+            .find_contiguous_aligned(block_size, block_size)
+            .expect("the pool has no room for the block below its limit");
+        self.claim_range(range);
+        range.min
+    }
+
+    /// Frees the block of `block_size` indices starting at `start` which
+    /// was allocated by `allocate_block_slot`. Returns Err without
+    /// changing anything if any of them are no longer in use.
+    ///
+    /// Panics if `block_size` is `0`.
+    pub fn free_block_slot(
+        &mut self,
+        start: usize,
+        block_size: usize,
+    ) -> Result<(), AlreadyReturned> {
+        assert!(block_size > 0, "cannot free an empty block");
+        let range = match start.checked_add(block_size - 1) {
+            Some(max) => Range { min: start, max },
+            None => return Err(AlreadyReturned),
+        };
+        if !self.is_range_used(range) {
+            return Err(AlreadyReturned);
+        }
+
+        self.release_range(range);
+        Ok(())
+    }
+
+    /// Finds the lowest `n` contiguous free indices whose first index is a
+    /// multiple of `align`, without allocating them. The returned range
+    /// does not include any padding needed to reach the alignment; the
+    /// indices skipped over stay free. Interior gaps are tried first,
+    /// then the tail, so this only returns `None` when the pool's limit
+    /// leaves no room. Pass the result to `request_range` to commit it.
+    ///
+    /// Panics if `n` or `align` is `0`.
+    pub fn find_contiguous_aligned(&self, n: usize, align: usize) -> Option<Range> {
+        assert!(n > 0, "cannot find an empty range");
+        assert!(align > 0, "alignment must be non-zero");
+
+        let fit = |start: usize, max: usize| {
+            let aligned = start.checked_add(align - 1)? / align * align;
+            let end = aligned.checked_add(n - 1)?;
+            if end <= max {
+                Some(Range {
+                    min: aligned,
+                    max: end,
+                })
+            } else {
+                None
+            }
+        };
+
+        let mut tail_start = self.next_id;
+        for &free in self.free_list.free_ranges() {
+            if free.max + 1 == self.next_id {
+                tail_start = free.min;
+            } else if let Some(range) = fit(free.min, free.max) {
+                return Some(range);
+            }
+        }
+
+        if self.limit == 0 {
+            return None;
+        }
+        fit(tail_start, self.limit - 1)
+    }
+
+    /// Finds the smallest free gap below `maximum()` which can hold `size`
+    /// contiguous indices, without allocating anything, and returns the
+    /// whole gap. The lowest gap wins a tie. Returns `None` if no gap is
+    /// large enough, in which case the tail would have to be extended.
+    ///
+    /// Panics if `size` is `0`.
+    pub fn smallest_fitting_gap(&self, size: usize) -> Option<Range> {
+        assert!(size > 0, "cannot find an empty range");
+        self.free_list
+            .free_ranges()
+            .filter(|free| free.max - free.min + 1 >= size)
+            .min_by_key(|free| free.max - free.min)
+            .cloned()
+    }
+
+    /// Allocates every index in `range`, e.g. one found by
+    /// `find_contiguous_aligned`. Returns Err without changing anything
+    /// if any of them are already in use.
+    ///
+    /// Panics if `range` extends beyond the pool's limit.
+    pub fn request_range(&mut self, range: Range) -> Result<(), AlreadyInUse> {
+        assert!(!range.empty(), "cannot request an empty range");
+        assert!(
+            range.max < self.limit,
+            "requested a range beyond the pool's limit"
+        );
+        if !self.is_free_range(range) {
+            return Err(AlreadyInUse);
+        }
+
+        self.claim_range(range);
+        Ok(())
+    }
+
+    /// Turns allocation order tracking on or off. While it is on, every
+    /// allocation is tagged with a monotonically increasing sequence
+    /// number which can be read back with `allocation_seq`, e.g. to find
+    /// the oldest live index for LRU eviction. Indices which are already
+    /// in use when tracking is turned on are numbered in ascending order.
+    ///
+    /// Tracking costs a `u64` per index up to `maximum()`.
+    pub fn track_allocation_seq(&mut self, enabled: bool) {
+        if !enabled {
+            self.allocation_seq = None;
+            return;
+        }
+        if self.allocation_seq.is_some() {
+            return;
+        }
+
+        // Number the existing runs directly; they aren't new allocations,
+        // so they mustn't reach the callbacks or the statistics
+        let mut seqs = vec![0; self.next_id];
+        let mut next_seq = self.next_seq;
+        for run in self.used_ranges() {
+            for seq in &mut seqs[run.min..run.max + 1] {
+                *seq = next_seq;
+                next_seq += 1;
+            }
+        }
+        self.allocation_seq = Some(seqs);
+        self.next_seq = next_seq;
+    }
+
+    /// Registers a callback which is called with every index the pool
+    /// hands out, whichever method allocated it. Replaces any previously
+    /// registered callback. There is no cost when none is registered.
+    #[inline]
+    pub fn set_on_allocate(&mut self, f: Callback) {
+        self.on_allocate = Some(f);
+    }
+
+    /// Registers a callback which is called with every index that is given
+    /// back to the pool, whichever method freed it. Replaces any
+    /// previously registered callback.
+    #[inline]
+    pub fn set_on_free(&mut self, f: Callback) {
+        self.on_free = Some(f);
+    }
+
+    /// Registers a callback which is called with the new `maximum()`
+    /// whenever it increases, e.g. to grow storage kept alongside the
+    /// indices exactly when needed. It fires once per operation with the
+    /// final maximum, not once per index for bulk allocations. Replaces
+    /// any previously registered callback.
+    #[inline]
+    pub fn set_on_grow(&mut self, f: Callback) {
+        self.on_grow = Some(f);
+        self.grown_to = self.next_id;
+    }
+
+    /// Unregisters the `set_on_allocate`, `set_on_free`,
+    /// `set_on_fragmented` and `set_on_grow` callbacks.
+    #[inline]
+    pub fn clear_callbacks(&mut self) {
+        self.on_allocate = None;
+        self.on_free = None;
+        self.on_fragmented = None;
+        self.on_grow = None;
+    }
+
+    /// Sets how many free ranges the pool may track before it is
+    /// considered too fragmented. Free ranges can't be merged without
+    /// moving indices, so the pool doesn't act on this itself; instead
+    /// `should_compact` starts returning true and the `set_on_fragmented`
+    /// callback fires, so you can compact on your own terms.
+    #[inline]
+    pub fn set_max_free_ranges(&mut self, limit: usize) {
+        self.max_free_ranges = limit;
+        self.fragmented = false;
+        self.check_fragmentation();
+    }
+
+    /// Returns true if there are more free ranges than the maximum set by
+    /// `set_max_free_ranges`.
+    #[inline]
+    pub fn should_compact(&self) -> bool {
+        self.free_ranges_count() > self.max_free_ranges
+    }
+
+    /// Registers a callback which is called with the free range count when
+    /// it rises above the maximum set by `set_max_free_ranges`. It fires
+    /// once each time the limit is crossed, not on every operation while
+    /// the pool stays fragmented. Replaces any previously registered
+    /// callback.
+    #[inline]
+    pub fn set_on_fragmented(&mut self, f: Callback) {
+        self.on_fragmented = Some(f);
+    }
+
+    /// Returns the sequence number `id` was tagged with when it was
+    /// allocated, or `None` if it is free or tracking is turned off.
+    /// Indices allocated later always have larger sequence numbers.
+    #[inline]
+    pub fn allocation_seq(&self, id: usize) -> Option<u64> {
+        match self.allocation_seq {
+            Some(ref seqs) => match seqs.get(id) {
+                Some(&seq) if seq != 0 => Some(seq),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    /// Returns the in-use index which was allocated longest ago, or `None`
+    /// if nothing is in use or allocation order tracking is turned off.
+    /// This is a linear scan over `[0..maximum())`.
+    pub fn oldest_in_use(&self) -> Option<usize> {
+        self.live_seqs()
+            .min_by_key(|&(_, seq)| seq)
+            .map(|(id, _)| id)
+    }
+
+    /// Returns the in-use index which was allocated most recently, or
+    /// `None` if nothing is in use or allocation order tracking is turned
+    /// off. This is a linear scan over `[0..maximum())`.
+    pub fn newest_in_use(&self) -> Option<usize> {
+        self.live_seqs()
+            .max_by_key(|&(_, seq)| seq)
+            .map(|(id, _)| id)
+    }
+
+    fn live_seqs(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.allocation_seq
+            .iter()
+            .flat_map(|seqs| seqs.iter().cloned().enumerate())
+            .filter(|&(_, seq)| seq != 0)
+    }
+
+    #[inline]
+    pub fn all_indices_after(&self, after: usize) -> iter::IndexAfterIter<'_, B> {
+        iter::IndexAfterIter::new(self.free_list.free_ranges_after(after), after, self.next_id)
+    }
+
+    /// Returns an iterator over every in-use index paired with a mutable
+    /// reference to its slot in `data`, e.g. to update the data of every
+    /// live entity in one pass. In-use indices are distinct, so each slot
+    /// is borrowed at most once; the slice is split up as the iterator
+    /// advances rather than indexed.
+    ///
+    /// Panics while iterating if `data` is too short to hold an in-use
+    /// index. `ensure_capacity` makes sure it isn't.
+    pub fn all_indices_mut_data<'a, 'd, T>(
+        &'a self,
+        data: &'d mut [T],
+    ) -> impl Iterator<Item = (usize, &'d mut T)> + 'a
+    where
+        'd: 'a,
+    {
+        let mut rest = data;
+        let mut offset = 0;
+        self.used_ranges().flat_map(move |run| {
+            let (head, tail) = mem::take(&mut rest).split_at_mut(run.max + 1 - offset);
+            let slots = &mut head[run.min - offset..];
+            rest = tail;
+            offset = run.max + 1;
+            (run.min..).zip(slots.iter_mut())
+        })
+    }
+
+    /// Returns up to the next `n` in-use indices, resuming from where the
+    /// previous call left off, so live indices can be processed in
+    /// bounded chunks. Once a pass is exhausted an empty `Vec` is
+    /// returned and the next call starts again from the lowest index.
+    /// Indices allocated behind the cursor aren't seen until then.
+    pub fn take_used(&mut self, n: usize) -> Vec<usize> {
+        let chunk: Vec<usize> = self.all_indices_after(self.used_cursor).take(n).collect();
+        self.used_cursor = match chunk.last() {
+            Some(&last) => last + 1,
+            None if n > 0 => 0,
+            None => self.used_cursor,
+        };
+        chunk
+    }
+
+    /// Moves the `take_used` cursor back to the start of the pool
+    #[inline]
+    pub fn reset_take_used(&mut self) {
+        self.used_cursor = 0;
+    }
+
+    /// Returns an iterator over the free ranges below `maximum()`, in
+    /// ascending order. It can be walked from either end, e.g. with
+    /// `.rev()` to look at the gaps nearest the tail first.
+    #[inline]
+    pub fn free_ranges(&self) -> impl DoubleEndedIterator<Item = Range> + ExactSizeIterator + '_ {
+        self.free_list.free_ranges().cloned()
+    }
+
+    /// Returns an iterator over the length of each free range below
+    /// `maximum()`, in ascending order of position, e.g. to build a
+    /// histogram of gap sizes.
+    #[inline]
+    pub fn gap_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.free_list
+            .free_ranges()
+            .map(|range| range.max - range.min + 1)
+    }
+
+    /// Returns an iterator over the free ranges which intersect
+    /// `[after, maximum())`, clamped to those bounds. This is the range
+    /// counterpart to `all_indices_after`.
+    #[inline]
+    pub fn free_ranges_after(&self, after: usize) -> impl Iterator<Item = Range> + '_ {
+        let end = self.next_id;
+        self.free_list
+            .free_ranges_after(after)
+            .cloned()
+            .take_while(move |range| range.min < end)
+            .map(move |range| Range {
+                min: cmp::max(range.min, after),
+                max: cmp::min(range.max, end - 1),
+            })
+    }
+
+    /// Returns an iterator over every free range in `[0..upper)`, treating
+    /// the pool as an arena of length `upper`. Interior gaps come first,
+    /// followed by the never-allocated tail `[maximum()..upper)` if it
+    /// isn't empty.
+    pub fn free_ranges_within(&self, upper: usize) -> impl Iterator<Item = Range> + '_ {
+        let tail = if upper > self.next_id {
+            Some(Range {
+                min: self.next_id,
+                max: upper - 1,
+            })
+        } else {
+            None
+        };
+
+        self.free_list
+            .free_ranges()
+            .cloned()
+            .take_while(move |range| range.min < upper)
+            .map(move |range| Range {
+                min: range.min,
+                max: cmp::min(range.max, upper - 1),
+            })
+            .chain(tail)
+    }
+
+    /// Counts the free indices in `[0..upper)`, treating the pool as an
+    /// arena of length `upper`: the interior gaps below `upper` plus the
+    /// tail `[maximum()..upper)`. This is how many more indices fit
+    /// before the arena overflows.
+    #[inline]
+    pub fn free_capacity_within(&self, upper: usize) -> usize {
+        self.free_ranges_within(upper)
+            .map(|range| range.max - range.min + 1)
+            .sum()
+    }
+
+    /// Controls whether freeing the highest indices shrinks `maximum()`.
+    /// This is on by default. With it off, freed indices at the end of the
+    /// pool are kept as an ordinary free range, so `maximum()` never goes
+    /// down (e.g. to avoid resizing buffers sized to it) until
+    /// `collapse_tail` or `normalize` is called.
+    #[inline]
+    pub fn set_auto_collapse(&mut self, enabled: bool) {
+        self.auto_collapse = enabled;
+        if self.collapsing() {
+            self.collapse_tail();
+        }
+    }
+
+    /// Whether frees should collapse the tail right away
+    #[inline]
+    fn collapsing(&self) -> bool {
+        self.auto_collapse && !self.defer_collapse
+    }
+
+    /// Runs `f` with tail collapsing deferred, then collapses the tail once
+    /// at the end, so a batch of `return_id` calls doesn't pay for it on
+    /// every call. The pool is usable as normal inside `f`, and collapsing
+    /// resumes even if `f` panics.
+    pub fn batch<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let was_deferred = self.defer_collapse;
+        self.defer_collapse = true;
+        let guard = CollapseOnDrop {
+            pool: self,
+            was_deferred,
+        };
+        f(guard.pool);
+    }
+
+    /// Copies the pool's state exactly as it is, without folding free
+    /// ranges at the end back into the tail. Callbacks aren't copied.
+    fn snapshot(&self) -> Self {
+        IndexPool {
+            next_id: self.next_id,
+            in_use: self.in_use,
+            prefix: self.prefix,
+            reserved: self.reserved,
+            limit: self.limit,
+            free_list: self.free_list.clone(),
+            allocation_seq: self.allocation_seq.clone(),
+            next_seq: self.next_seq,
+            on_allocate: None,
+            on_free: None,
+            max_free_ranges: self.max_free_ranges,
+            on_fragmented: None,
+            fragmented: self.fragmented,
+            on_grow: None,
+            grown_to: self.next_id,
+            defer_grow: false,
+            auto_collapse: self.auto_collapse,
+            defer_collapse: false,
+            peak_in_use: self.peak_in_use,
+            peak_maximum: self.peak_maximum,
+            total_allocations: self.total_allocations,
+            total_frees: self.total_frees,
+            recycled_allocations: self.recycled_allocations,
+            used_cursor: self.used_cursor,
+        }
+    }
+
+    /// Overwrites this pool's state with `source`'s exactly as it is,
+    /// keeping this pool's callbacks and whether it's inside a `batch`.
+    fn restore(&mut self, source: &Self) {
+        self.next_id = source.next_id;
+        self.grown_to = source.next_id;
+        self.in_use = source.in_use;
+        self.prefix = source.prefix;
+        self.reserved = source.reserved;
+        self.limit = source.limit;
+        self.free_list.clone_from(&source.free_list);
+        self.allocation_seq.clone_from(&source.allocation_seq);
+        self.next_seq = source.next_seq;
+        self.max_free_ranges = source.max_free_ranges;
+        self.fragmented = source.fragmented;
+        self.auto_collapse = source.auto_collapse;
+        self.peak_in_use = source.peak_in_use;
+        self.peak_maximum = source.peak_maximum;
+        self.total_allocations = source.total_allocations;
+        self.total_frees = source.total_frees;
+        self.recycled_allocations = source.recycled_allocations;
+        self.used_cursor = source.used_cursor;
+    }
+
+    /// Runs `f` speculatively: if it returns `None`, every change it made
+    /// to the pool is undone, including the statistics, and if it returns
+    /// `Some` the changes are kept. Callbacks which fired inside `f` can't
+    /// be taken back. The pool's state is copied up front, so this costs
+    /// as much as a `clone`. Rolling back restores the layout exactly, even
+    /// inside a `batch`.
+    pub fn try_scope<R, F>(&mut self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Self) -> Option<R>,
+    {
+        let snapshot = self.snapshot();
+        let result = f(self);
+        if result.is_none() {
+            self.restore(&snapshot);
+        }
+        result
+    }
+
+    /// Folds any free range at the end of the pool back into the tail,
+    /// lowering `maximum()`. Returns how far `maximum()` went down. Only
+    /// needed when auto collapse has been turned off with
+    /// `set_auto_collapse`.
+    pub fn collapse_tail(&mut self) -> usize {
+        let old_max = self.next_id;
+        while self.collapse_next() {}
+        self.check_fragmentation();
+        old_max - self.next_id
+    }
+
+    /// Like `collapse_tail`, but only collapses if at least `min_reclaim`
+    /// indices would be reclaimed, so that small free tails are left in
+    /// place rather than resizing buffers for them. Returns how far
+    /// `maximum()` went down, or `0` if it declined.
+    pub fn collapse_if_below(&mut self, min_reclaim: usize) -> usize {
+        let mut tail_start = self.next_id;
+        for free in self.free_list.free_ranges().rev() {
+            if free.max + 1 != tail_start {
+                break;
+            }
+            tail_start = free.min;
+        }
+
+        let reclaimable = self.next_id - tail_start;
+        if reclaimable == 0 || reclaimable < min_reclaim {
+            return 0;
+        }
+        self.collapse_tail()
+    }
+
+    /// Like `collapse_tail`, but never lowers `maximum()` below
+    /// `min_capacity`, e.g. the size buffers have been allocated for. Free
+    /// indices between the floor and the old `maximum()` are reclaimed,
+    /// while those below the floor stay an ordinary free range. Returns
+    /// how far `maximum()` went down.
+    pub fn shrink_to(&mut self, min_capacity: usize) -> usize {
+        let old_max = self.next_id;
+        loop {
+            let last = match self.free_list.free_ranges().next_back() {
+                Some(&last) => last,
+                None => break,
+            };
+            let new_end = cmp::max(last.min, min_capacity);
+            if last.max + 1 != self.next_id || new_end >= self.next_id {
+                break;
+            }
+
+            self.free_list.remove_last_contiguous();
+            if new_end > last.min {
+                self.free_list.set_range_free(Range {
+                    min: last.min,
+                    max: new_end - 1,
+                });
+            }
+            self.next_id = new_end;
+            self.grown_to = new_end;
+        }
+        debug_assert!(self.check_range_invariants(), "{}", self.debug_layout());
+        self.check_fragmentation();
+        old_max - self.next_id
+    }
+
+    /// Advances `maximum()` to the next multiple of `align`, marking the
+    /// skipped indices as free, so that fresh indices from the tail start
+    /// aligned, e.g. before handing the tail to another allocator. The
+    /// padding can still be handed out by `new_id`. Does nothing if
+    /// `maximum()` is already aligned.
+    ///
+    /// The padding is a free range at the end of the pool, which auto
+    /// collapse would fold straight back into the tail, so this needs
+    /// auto-collapse to be turned off with `set_auto_collapse(false)`.
+    /// Calling `collapse_tail` undoes it.
+    ///
+    /// Panics if auto-collapse is on, if `align` isn't a power of two, or
+    /// if the aligned `maximum()` would lie past the pool's limit.
+    pub fn align_tail(&mut self, align: usize) {
+        assert!(
+            !self.auto_collapse,
+            "aligning the tail needs auto-collapse to be off"
+        );
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        let aligned = self
+            .next_id
+            .checked_add(align - 1)
+            .map(|end| end & !(align - 1))
+            .filter(|&end| end <= self.limit)
+            .expect("the pool has no room to align its tail below its limit");
+        if aligned == self.next_id {
+            return;
+        }
+
+        self.free_list.set_range_free(Range {
+            min: self.next_id,
+            max: aligned - 1,
+        });
+        self.next_id = aligned;
+        self.peak_maximum = cmp::max(self.peak_maximum, self.next_id);
+        self.check_fragmentation();
+        self.check_grow();
+    }
+
+    /// Moves the whole index space up so that the pool's base, the
+    /// `prefix_len()` reserved by `with_initial_index`, becomes
+    /// `new_base`, e.g. to make room for another pool's indices when
+    /// merging two of them. Every in-use and free index moves up by the
+    /// same offset, and the indices below `new_base` join the reserved
+    /// prefix. Use the returned `RebaseMap` to move any data kept
+    /// alongside the indices.
+    ///
+    /// Panics if `new_base` is below `prefix_len()`, or if the shifted
+    /// `maximum()` would lie past the pool's limit.
+    pub fn rebase(&mut self, new_base: usize) -> RebaseMap {
+        assert!(
+            new_base >= self.prefix,
+            "cannot rebase a pool below its current base"
+        );
+        let offset = new_base - self.prefix;
+        let next_id = self
+            .next_id
+            .checked_add(offset)
+            .filter(|&end| end <= self.limit)
+            .expect("the pool has no room to rebase below its limit");
+        if offset == 0 {
+            return RebaseMap { offset };
+        }
+
+        let ranges: Vec<Range> = self.free_list.free_ranges().cloned().collect();
+        self.free_list.clear();
+        for range in ranges {
+            self.free_list.set_range_free(Range {
+                min: range.min + offset,
+                max: range.max + offset,
+            });
+        }
+        if let Some(ref mut seqs) = self.allocation_seq {
+            seqs.splice(0..0, std::iter::repeat(0).take(offset));
+        }
+
+        self.next_id = next_id;
+        self.prefix = new_base;
+        self.in_use += offset;
+        if self.used_cursor > 0 {
+            self.used_cursor += offset;
+        }
+        self.peak_in_use = cmp::max(self.peak_in_use, self.in_use);
+        self.peak_maximum = cmp::max(self.peak_maximum, self.next_id);
+        self.check_grow();
+        RebaseMap { offset }
+    }
+
+    /// Tidies the pool's internal representation without moving any
+    /// indices: any free range at the end of the pool is folded back into
+    /// the tail. Unlike a compaction, `all_indices()` is exactly the same
+    /// afterwards, so no data needs to be migrated.
+    pub fn normalize(&mut self) {
+        while self.collapse_next() {}
+        self.check_fragmentation();
+    }
+
+    /// Checks that every free range is well formed: each one has
+    /// `min <= max`, they're in ascending order with at least one used
+    /// index between neighbours (touching ranges should have been
+    /// merged), and none reach past `maximum()`. This is a cheap probe for
+    /// bugs in code that mutates the free list, and should always be true.
+    pub fn check_range_invariants(&self) -> bool {
+        let mut prev: Option<Range> = None;
+        for &range in self.free_list.free_ranges() {
+            if range.min > range.max || range.max >= self.next_id {
+                return false;
+            }
+            if let Some(prev) = prev {
+                if prev.max >= range.min || prev.max + 1 == range.min {
+                    return false;
+                }
+            }
+            prev = Some(range);
+        }
+        true
+    }
+
+    /// Panics with the pool's layout if `check_range_invariants` fails
+    #[inline]
+    pub fn assert_ranges_sorted(&self) {
+        assert!(
+            self.check_range_invariants(),
+            "free ranges are out of order or uncoalesced: {}",
+            self.debug_layout()
+        );
+    }
+
+    /// Finds the lowest `n` contiguous free indices, spilling over into
+    /// the tail if no interior gap is large enough
+    fn find_contiguous(&self, n: usize) -> Option<Range> {
+        self.find_contiguous_aligned(n, 1)
+    }
+
+    /// Checks whether every index in `range` is in use
+    fn is_range_used(&self, range: Range) -> bool {
+        range.max < self.next_id
+            && self
+                .free_list
+                .free_ranges_after(range.min)
+                .next()
+                .map_or(true, |free| free.min > range.max)
+    }
+
+    /// Marks a range which is known to be entirely free as used
+    fn claim_range(&mut self, range: Range) {
+        let below_tail = cmp::min(range.max + 1, self.next_id);
+        for id in range.min..below_tail {
+            self.free_list.set_used(id);
+        }
+
+        if range.max >= self.next_id {
+            if range.min > self.next_id {
+                self.free_list.set_range_free(Range {
+                    min: self.next_id,
+                    max: range.min - 1,
+                });
+            }
+            self.next_id = range.max + 1;
+        }
+
+        self.in_use += range.max - range.min + 1;
+        self.on_allocated(range);
+    }
+
+    /// Frees whichever indices in `range` are still in use
+    fn release_used_within(&mut self, range: Range) {
+        let runs: Vec<Range> = self
+            .used_ranges()
+            .filter(|run| run.max >= range.min && run.min <= range.max)
+            .map(|run| Range {
+                min: cmp::max(run.min, range.min),
+                max: cmp::min(run.max, range.max),
+            })
+            .collect();
+        for &run in runs.iter().rev() {
+            self.release_range(run);
+        }
+    }
+
+    /// Frees a range which is known to be entirely in use
+    fn release_range(&mut self, range: Range) {
+        self.free_list.set_range_free(range);
+        self.in_use -= range.max - range.min + 1;
+        self.on_freed(range);
+
+        if self.collapsing() {
+            while self.collapse_next() {}
+        }
+        self.check_fragmentation();
+    }
+
+    /// Bookkeeping shared by every path which allocates indices
+    #[inline]
+    fn on_allocated(&mut self, range: Range) {
+        self.total_allocations += (range.max - range.min + 1) as u64;
+        self.peak_in_use = cmp::max(self.peak_in_use, self.in_use);
+        self.peak_maximum = cmp::max(self.peak_maximum, self.next_id);
+
+        if let Some(ref mut callback) = self.on_allocate {
+            for id in range.min..range.max + 1 {
+                callback(id);
+            }
+        }
+        if let Some(ref mut seqs) = self.allocation_seq {
+            if seqs.len() <= range.max {
+                seqs.resize(range.max + 1, 0);
+            }
+            for seq in &mut seqs[range.min..range.max + 1] {
+                *seq = self.next_seq;
+                self.next_seq += 1;
+            }
+        }
+
+        // Splitting a free range or skipping ahead can add free ranges too
+        self.check_fragmentation();
+        self.check_grow();
+    }
+
+    /// Fires the grow callback if `next_id` has risen since it last
+    /// changed
+    #[inline]
+    fn check_grow(&mut self) {
+        if !self.defer_grow && self.next_id > self.grown_to {
+            self.grown_to = self.next_id;
+            if let Some(ref mut callback) = self.on_grow {
+                callback(self.next_id);
+            }
+        }
+    }
+
+    /// Runs `f` with the grow callback held back, then fires it once with
+    /// the final maximum, so bulk allocations built out of single ones
+    /// report a single grow
+    fn deferring_grow<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        let was_deferred = self.defer_grow;
+        self.defer_grow = true;
+        let guard = GrowOnDrop {
+            pool: self,
+            was_deferred,
+        };
+        f(guard.pool)
+    }
+
+    /// Fires the fragmentation callback when the free range count first
+    /// rises above the configured maximum
+    #[inline]
+    fn check_fragmentation(&mut self) {
+        if self.max_free_ranges == usize::MAX {
+            return;
+        }
+
+        let count = self.free_ranges_count();
+        let fragmented = count > self.max_free_ranges;
+        if fragmented && !self.fragmented {
+            if let Some(ref mut callback) = self.on_fragmented {
+                callback(count);
+            }
+        }
+        self.fragmented = fragmented;
+    }
+
+    /// Bookkeeping shared by every path which frees indices
+    #[inline]
+    fn on_freed(&mut self, range: Range) {
+        self.total_frees += (range.max - range.min + 1) as u64;
+        if let Some(ref mut callback) = self.on_free {
+            for id in range.min..range.max + 1 {
+                callback(id);
+            }
+        }
+        if let Some(ref mut seqs) = self.allocation_seq {
+            let end = cmp::min(range.max + 1, seqs.len());
+            if range.min < end {
+                for seq in &mut seqs[range.min..end] {
+                    *seq = 0;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn collapse_next(&mut self) -> bool {
+        let last_range = self.free_list.free_ranges().next_back().cloned();
+        if let Some(last_range) = last_range {
+            if last_range.max + 1 == self.next_id {
+                self.free_list.remove_last_contiguous();
+                self.next_id = last_range.min;
+                self.grown_to = self.next_id;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        if self.on_free.is_some() {
+            let runs: Vec<Range> = self.used_ranges().collect();
+            for run in runs {
+                self.on_freed(run);
+            }
+        } else {
+            self.total_frees += self.in_use as u64;
+        }
+        if let Some(ref mut seqs) = self.allocation_seq {
+            seqs.clear();
+        }
+        self.free_list.clear();
+        self.in_use = 0;
+        self.prefix = 0;
+        self.next_id = 0;
+        self.grown_to = 0;
+        self.used_cursor = 0;
+        self.fragmented = false;
+    }
+}
+
+impl<B: FreeListBackend> Clone for IndexPool<B> {
+    /// Copies the pool's state. Callbacks can't be cloned, so the new pool
+    /// starts with none registered.
+    #[inline]
+    fn clone(&self) -> Self {
+        let mut pool = self.snapshot();
+
+        // The clone isn't part of any `batch` this pool is in the middle of
+        if pool.collapsing() {
+            while pool.collapse_next() {}
+        }
+        pool
+    }
+
+    /// Overwrites this pool with the state of `source`, reusing this
+    /// pool's allocations where the free list backend allows it. This
+    /// pool's own callbacks are kept.
+    fn clone_from(&mut self, source: &Self) {
+        self.restore(source);
+        if self.collapsing() {
+            while self.collapse_next() {}
+        }
+    }
+}
+
+/// Gives an index back to the pool when dropped, unless forgotten, and
+/// lowers `maximum()` back to what it was before the index was allocated
+struct ReturnOnUnwind<'a, B: FreeListBackend> {
+    pool: &'a mut IndexPool<B>,
+    id: usize,
+    old_max: usize,
+}
+
+impl<'a, B: FreeListBackend> Drop for ReturnOnUnwind<'a, B> {
+    fn drop(&mut self) {
+        let _ = self.pool.return_id(self.id);
+        // Without auto-collapse, allocating past the tail leaves a gap
+        self.pool.shrink_to(self.old_max);
+    }
+}
+
+/// Ends a `batch`, collapsing the tail if it's no longer being deferred
+struct CollapseOnDrop<'a, B: FreeListBackend> {
+    pool: &'a mut IndexPool<B>,
+    was_deferred: bool,
+}
+
+impl<'a, B: FreeListBackend> Drop for CollapseOnDrop<'a, B> {
+    fn drop(&mut self) {
+        self.pool.defer_collapse = self.was_deferred;
+        if self.pool.collapsing() {
+            while self.pool.collapse_next() {}
+            self.pool.check_fragmentation();
+        }
+    }
+}
+
+/// Ends a `deferring_grow`, firing the grow callback if it's no longer
+/// being held back
+struct GrowOnDrop<'a, B: FreeListBackend> {
+    pool: &'a mut IndexPool<B>,
+    was_deferred: bool,
+}
+
+impl<'a, B: FreeListBackend> Drop for GrowOnDrop<'a, B> {
+    fn drop(&mut self) {
+        self.pool.defer_grow = self.was_deferred;
+        self.pool.check_grow();
+    }
+}
+
+/// The number of free ranges printed by `{:?}` before the rest are elided
+const DEBUG_FREE_RANGES: usize = 8;
+
+impl<B: FreeListBackend> fmt::Debug for IndexPool<B> {
+    /// Prints the pool's counters and its first few free ranges. Use the
+    /// alternate `{:#?}` form to print every free range.
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        struct FreeList<'a, B>(&'a B);
+
+        impl<'a, B: FreeListBackend> fmt::Debug for FreeList<'a, B> {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                let free_list = self.0;
+                let count = free_list.free_ranges().len();
+                let shown = if fmt.alternate() {
+                    count
+                } else {
+                    cmp::min(count, DEBUG_FREE_RANGES)
+                };
+
+                let mut list = fmt.debug_list();
+                list.entries(free_list.free_ranges().take(shown));
+                if shown < count {
+                    list.entry(&format_args!("...{} more", count - shown));
+                }
+                list.finish()
+            }
+        }
+
+        let mut debug = fmt.debug_struct("IndexPool");
+        debug
+            .field("next_id", &self.next_id)
+            .field("in_use", &self.in_use);
+        if let Some(limit) = self.limit() {
+            debug.field("limit", &limit);
+        }
+        debug
+            .field("free_list", &FreeList(&self.free_list))
+            .finish()
+    }
+}
+
+impl<B: FreeListBackend> Default for IndexPool<B> {
+    /// Constructs an empty IndexPool. Indices will start at `0`.
+    #[inline]
+    fn default() -> Self {
+        IndexPool::with_backend()
+    }
+}
+
+/// A snapshot of an `IndexPool`'s metrics, as returned by `stats()`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PoolStats {
+    /// The number of indices currently in use
+    pub in_use: usize,
+    /// The value of `maximum()`
+    pub maximum: usize,
+    /// The number of free indices below `maximum()`
+    pub free_count: usize,
+    /// The number of contiguous free ranges below `maximum()`
+    pub free_ranges_count: usize,
+    /// The length of the largest free range, or `0` if there are none
+    pub largest_free_gap: usize,
+    /// How scattered the free indices are, from `0.0` when they all sit
+    /// in one range (or there are none) towards `1.0` as they're spread
+    /// over many small ranges. Computed as
+    /// `1 - largest_free_gap / free_count`.
+    pub fragmentation: f64,
+}
+
+/// The indices allocated by `IndexPool::new_ids_compact`.
+#[derive(Clone, Debug)]
+pub enum NewIds {
+    /// The indices form a single contiguous run
+    Contiguous(Range),
+    /// The indices are spread over several runs, in allocation order
+    Scattered(Vec<usize>),
+}
+
+/// How `IndexPool::rebase` moved the pool's indices. Every index moved
+/// up by the same offset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RebaseMap {
+    offset: usize,
+}
+
+impl RebaseMap {
+    /// How far every index moved up
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The index which `old` moved to
+    #[inline]
+    pub fn map(&self, old: usize) -> usize {
+        old + self.offset
+    }
+}
+
+/// A handle to a block of contiguous indices allocated by
+/// `IndexPool::allocate_block`. It can't be copied, so a block can only
+/// be given back to the pool once.
+#[derive(Debug)]
+pub struct BlockId {
+    range: Range,
+}
+
+impl BlockId {
+    /// The indices which were allocated for this block
+    #[inline]
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// The first index of the block
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.range.min
+    }
+
+    /// The number of indices in the block
+    #[inline]
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.range.max - self.range.min + 1
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AlreadyReturned;
+
+impl fmt::Display for AlreadyReturned {
+    #[allow(deprecated)]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for AlreadyReturned {
+    fn description(&self) -> &str {
+        "An index was tried to be returned to the pool, but it was already marked as free."
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AlreadyInUse;
+
+impl fmt::Display for AlreadyInUse {
+    #[allow(deprecated)]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for AlreadyInUse {
+    fn description(&self) -> &str {
+        "An index was requested which was already marked as in use."
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PoolFull;
+
+impl fmt::Display for PoolFull {
+    #[allow(deprecated)]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for PoolFull {
+    fn description(&self) -> &str {
+        "An index was requested, but every index below the pool's limit is in use."
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidState;
+
+impl fmt::Display for InvalidState {
+    #[allow(deprecated)]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for InvalidState {
+    fn description(&self) -> &str {
+        "The free ranges were unsorted, overlapping, or extended past the end of the pool."
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RelocateError {
+    /// The index being moved from was not in use
+    NotInUse,
+    /// The index being moved to was already in use
+    AlreadyInUse,
+    /// The index being moved to was not below the pool's limit
+    OutOfBounds,
+}
+
+impl fmt::Display for RelocateError {
+    #[allow(deprecated)]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for RelocateError {
+    fn description(&self) -> &str {
+        match *self {
+            RelocateError::NotInUse => "An index was relocated which was not in use.",
+            RelocateError::AlreadyInUse => {
+                "An index was relocated onto one which was already marked as in use."
+            }
+            RelocateError::OutOfBounds => "An index was relocated beyond the pool's limit.",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestError {
+    /// The index was already marked as in use
+    AlreadyInUse,
+    /// The index was not below the pool's limit
+    OutOfBounds,
+}
+
+impl fmt::Display for RequestError {
+    #[allow(deprecated)]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for RequestError {
+    fn description(&self) -> &str {
+        match *self {
+            RequestError::AlreadyInUse => {
+                "An index was requested which was already marked as in use."
+            }
+            RequestError::OutOfBounds => "An index was requested beyond the pool's limit.",
+        }
     }
 }
@@ -0,0 +1,189 @@
+//! An `IndexPool` whose indices are tagged with a type, so that indices
+//! from different pools can't be mixed up.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use {AlreadyInUse, AlreadyReturned, IndexPool};
+
+/// An index allocated from a `TypedIndexPool<Tag>`. It is a plain `usize`
+/// at runtime, but indices with different tags are different types.
+pub struct Index<Tag> {
+    index: usize,
+    _tag: PhantomData<fn() -> Tag>,
+}
+
+impl<Tag> Index<Tag> {
+    /// Tags a raw index, e.g. one loaded from disk
+    #[inline]
+    pub fn from_raw(index: usize) -> Self {
+        Index {
+            index,
+            _tag: PhantomData,
+        }
+    }
+
+    /// The raw index, e.g. for indexing a `Vec`
+    #[inline]
+    pub fn raw(self) -> usize {
+        self.index
+    }
+}
+
+impl<Tag> Clone for Index<Tag> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Tag> Copy for Index<Tag> {}
+
+impl<Tag> PartialEq for Index<Tag> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<Tag> Eq for Index<Tag> {}
+
+impl<Tag> PartialOrd for Index<Tag> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Tag> Ord for Index<Tag> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl<Tag> Hash for Index<Tag> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<Tag> fmt::Debug for Index<Tag> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Index({})", self.index)
+    }
+}
+
+/// An `IndexPool` which hands out `Index<Tag>` instead of `usize`. `Tag`
+/// is usually an empty marker type, and costs nothing at runtime.
+///
+/// ```compile_fail
+/// use index_pool::typed::TypedIndexPool;
+///
+/// struct Entity;
+/// struct Component;
+///
+/// let mut entities = TypedIndexPool::<Entity>::new();
+/// let mut components = TypedIndexPool::<Component>::new();
+///
+/// let entity = entities.new_id();
+/// components.return_id(entity).unwrap(); // Mismatched tags
+/// ```
+pub struct TypedIndexPool<Tag> {
+    pool: IndexPool,
+    _tag: PhantomData<fn() -> Tag>,
+}
+
+impl<Tag> TypedIndexPool<Tag> {
+    /// Constructs an empty pool. Indices will start at `0`.
+    #[inline]
+    pub fn new() -> Self {
+        TypedIndexPool::from_pool(IndexPool::new())
+    }
+
+    /// Wraps an untyped pool, tagging all of its indices
+    #[inline]
+    pub fn from_pool(pool: IndexPool) -> Self {
+        TypedIndexPool {
+            pool,
+            _tag: PhantomData,
+        }
+    }
+
+    /// See `IndexPool::new_id`
+    #[inline]
+    pub fn new_id(&mut self) -> Index<Tag> {
+        Index::from_raw(self.pool.new_id())
+    }
+
+    /// See `IndexPool::request_id`
+    #[inline]
+    pub fn request_id(&mut self, id: Index<Tag>) -> Result<(), AlreadyInUse> {
+        self.pool.request_id(id.index)
+    }
+
+    /// See `IndexPool::return_id`
+    #[inline]
+    pub fn return_id(&mut self, id: Index<Tag>) -> Result<(), AlreadyReturned> {
+        self.pool.return_id(id.index)
+    }
+
+    /// See `IndexPool::is_free`
+    #[inline]
+    pub fn is_free(&self, id: Index<Tag>) -> bool {
+        self.pool.is_free(id.index)
+    }
+
+    /// See `IndexPool::maximum`
+    #[inline]
+    pub fn maximum(&self) -> usize {
+        self.pool.maximum()
+    }
+
+    /// See `IndexPool::in_use`
+    #[inline]
+    pub fn in_use(&self) -> usize {
+        self.pool.in_use()
+    }
+
+    /// Returns an iterator over all indices which are in use
+    #[inline]
+    pub fn all_indices(&self) -> impl Iterator<Item = Index<Tag>> + '_ {
+        self.pool.all_indices().map(Index::from_raw)
+    }
+
+    /// The untyped pool underneath, for operations which aren't forwarded
+    #[inline]
+    pub fn as_pool(&self) -> &IndexPool {
+        &self.pool
+    }
+
+    /// Unwraps the untyped pool underneath
+    #[inline]
+    pub fn into_pool(self) -> IndexPool {
+        self.pool
+    }
+}
+
+impl<Tag> Default for TypedIndexPool<Tag> {
+    #[inline]
+    fn default() -> Self {
+        TypedIndexPool::new()
+    }
+}
+
+impl<Tag> Clone for TypedIndexPool<Tag> {
+    #[inline]
+    fn clone(&self) -> Self {
+        TypedIndexPool::from_pool(self.pool.clone())
+    }
+}
+
+impl<Tag> fmt::Debug for TypedIndexPool<Tag> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("TypedIndexPool").field(&self.pool).finish()
+    }
+}
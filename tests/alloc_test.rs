@@ -0,0 +1,46 @@
+extern crate index_pool;
+use index_pool::backend::VecFreeRanges;
+use index_pool::IndexPool;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts every allocation made by this test binary. There's only one test
+/// in this file, so nothing else allocates while it's counting.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn clone_from_reuses_vec_backend() {
+    let mut source = IndexPool::<VecFreeRanges>::with_backend();
+    source.track_allocation_seq(true);
+    for _ in 0..64 {
+        source.new_id();
+    }
+    for id in (0..60).step_by(3) {
+        source.return_id(id).unwrap();
+    }
+
+    let mut pool = source.clone();
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for _ in 0..10 {
+        pool.clone_from(&source);
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before);
+    assert!(pool.all_indices().eq(source.all_indices()));
+}
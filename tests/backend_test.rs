@@ -0,0 +1,75 @@
+extern crate index_pool;
+use index_pool::backend::VecFreeRanges;
+use index_pool::{FreeListBackend, FreeRanges, IndexPool, Range};
+
+fn ranges<B: FreeListBackend>(free: &B) -> Vec<(usize, usize)> {
+    free.free_ranges().map(|r| (r.min, r.max)).collect()
+}
+
+#[test]
+fn vec_backend_merges_and_splits() {
+    let mut free = VecFreeRanges::new();
+    assert!(free.set_range_free(Range { min: 2, max: 4 }));
+    assert!(free.set_range_free(Range { min: 8, max: 9 }));
+    assert!(free.set_free(6));
+    assert!(!free.set_free(3));
+    assert!(!free.set_range_free(Range { min: 2, max: 3 }));
+    assert_eq!(ranges(&free), vec![(2, 4), (6, 6), (8, 9)]);
+
+    // Filling the holes merges all three ranges into one
+    assert!(free.set_range_free(Range { min: 5, max: 7 }));
+    assert_eq!(ranges(&free), vec![(2, 9)]);
+
+    assert!(free.set_used(5));
+    assert!(!free.set_used(5));
+    assert_eq!(ranges(&free), vec![(2, 4), (6, 9)]);
+    assert_eq!(free.set_first_used(), Some(2));
+    assert_eq!(free.last(), Some(9));
+
+    let after: Vec<_> = free.free_ranges_after(7).map(|r| (r.min, r.max)).collect();
+    assert_eq!(after, vec![(6, 9)]);
+
+    free.remove_last_contiguous();
+    assert_eq!(ranges(&free), vec![(3, 4)]);
+    assert!(free.is_free(4) && !free.is_free(5));
+}
+
+#[test]
+fn range_spanning_free_ranges() {
+    fn check<B: FreeListBackend>(mut free: B) {
+        assert!(free.set_range_free(Range { min: 2, max: 3 }));
+        assert!(free.set_range_free(Range { min: 6, max: 7 }));
+        assert!(free.set_range_free(Range { min: 1, max: 8 }));
+        assert_eq!(ranges(&free), vec![(1, 8)]);
+        assert!(!free.set_range_free(Range { min: 3, max: 6 }));
+
+        assert!(free.set_range_free(Range { min: 12, max: 12 }));
+        assert!(free.set_range_free(Range { min: 5, max: 14 }));
+        assert_eq!(ranges(&free), vec![(1, 14)]);
+    }
+
+    check(FreeRanges::new());
+    check(VecFreeRanges::new());
+}
+
+#[test]
+fn pool_with_vec_backend() {
+    let mut pool = IndexPool::<VecFreeRanges>::with_backend();
+    let mut reference = IndexPool::new();
+    for _ in 0..20 {
+        pool.new_id();
+        reference.new_id();
+    }
+    for &id in &[3, 4, 5, 10, 19, 18, 12] {
+        pool.return_id(id).unwrap();
+        reference.return_id(id).unwrap();
+    }
+    pool.request_id(30).unwrap();
+    reference.request_id(30).unwrap();
+
+    assert!(pool.same_free_set(&reference));
+    assert!(pool.all_indices().eq(reference.all_indices()));
+    assert!(pool.all_indices_after(8).eq(reference.all_indices_after(8)));
+    assert_eq!(pool.in_use(), reference.in_use());
+    assert_eq!(pool.new_id(), 3);
+}
@@ -1,5 +1,5 @@
 extern crate index_pool;
-use index_pool::IndexPool;
+use index_pool::{AlreadyReturned, IndexPool, NewIds, Range};
 
 #[test]
 fn basic_test() {
@@ -141,6 +141,40 @@ fn iter_after() {
     assert_eq!(iter.next(), None);
 }
 
+#[test]
+fn indices_after_maximum() {
+    let mut pool = IndexPool::new();
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    pool.return_id(5).unwrap();
+
+    for &after in &[8, 108, usize::MAX] {
+        assert_eq!(pool.all_indices_after(after).count(), 0, "after {}", after);
+    }
+    assert!(pool.all_indices_after(7).eq(vec![7]));
+
+    // After the pool shrinks, an old cursor lies past the end
+    pool.return_id(7).unwrap();
+    pool.return_id(6).unwrap();
+    assert_eq!(pool.maximum(), 5);
+    assert_eq!(pool.all_indices_after(6).count(), 0);
+    assert_eq!(pool.all_indices_after(5).count(), 0);
+
+    // A cursor inside a trailing free range when auto collapse is off
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    for id in 4..8 {
+        pool.return_id(id).unwrap();
+    }
+    assert_eq!(pool.all_indices_after(5).count(), 0);
+    assert_eq!(pool.all_indices_after(usize::MAX).count(), 0);
+    assert!(pool.all_indices_after(2).eq(vec![2, 3]));
+}
+
 #[test]
 fn request_sequential() {
     let mut pool = IndexPool::new();
@@ -156,3 +190,1488 @@ fn request_sequential() {
     assert_eq!(pool.request_id(9), Ok(()));
     assert!(!pool.is_free(9));
 }
+
+#[test]
+fn first_n_free_preview() {
+    let mut pool = IndexPool::new();
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+    pool.return_id(3).unwrap();
+    pool.return_id(6).unwrap();
+
+    let preview = pool.first_n_free(5);
+    assert_eq!(preview, vec![2, 3, 6, 8, 9]);
+
+    // Previewing must not change anything
+    assert_eq!(pool.in_use(), 5);
+    assert_eq!(pool.maximum(), 8);
+
+    let allocated: Vec<usize> = (0..5).map(|_| pool.new_id()).collect();
+    assert_eq!(allocated, preview);
+
+    assert!(pool.first_n_free(0).is_empty());
+}
+
+#[test]
+fn free_ranges_after_clamps() {
+    let mut pool = IndexPool::new();
+    for _ in 0..12 {
+        pool.new_id();
+    }
+    for id in &[2, 3, 4, 5, 8, 9] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let ranges: Vec<_> = pool.free_ranges_after(4).map(|r| (r.min, r.max)).collect();
+    assert_eq!(ranges, vec![(4, 5), (8, 9)]);
+
+    let ranges: Vec<_> = pool.free_ranges_after(0).map(|r| (r.min, r.max)).collect();
+    assert_eq!(ranges, vec![(2, 5), (8, 9)]);
+
+    assert_eq!(pool.free_ranges_after(10).count(), 0);
+    assert_eq!(pool.free_ranges_after(100).count(), 0);
+}
+
+#[test]
+fn stats() {
+    let mut pool = IndexPool::new();
+    let stats = pool.stats();
+    assert_eq!(stats.in_use, 0);
+    assert_eq!(stats.maximum, 0);
+    assert_eq!(stats.free_count, 0);
+    assert_eq!(stats.free_ranges_count, 0);
+    assert_eq!(stats.largest_free_gap, 0);
+    assert_eq!(stats.fragmentation, 0.0);
+
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in &[1, 4, 5, 6, 8] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let stats = pool.stats();
+    assert_eq!(stats.in_use, 5);
+    assert_eq!(stats.maximum, 10);
+    assert_eq!(stats.free_count, 5);
+    assert_eq!(stats.free_ranges_count, 3);
+    assert_eq!(stats.largest_free_gap, 3);
+    assert!((stats.fragmentation - 0.4).abs() < 1e-9);
+}
+
+#[test]
+fn peaks_and_totals() {
+    let mut pool = IndexPool::new();
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    pool.request_id(11).unwrap();
+    for id in &[1, 2, 3, 11] {
+        pool.return_id(*id).unwrap();
+    }
+    pool.new_id();
+
+    assert_eq!(pool.peak_in_use(), 9);
+    assert_eq!(pool.peak_maximum(), 12);
+    assert_eq!(pool.total_allocations(), 10);
+    assert_eq!(pool.total_frees(), 4);
+    assert_eq!(
+        pool.total_allocations() - pool.total_frees(),
+        pool.in_use() as u64
+    );
+
+    // Turning on sequence tracking doesn't count as allocating anything
+    pool.track_allocation_seq(true);
+    assert_eq!(pool.total_allocations(), 10);
+
+    pool.reset_statistics();
+    assert_eq!(pool.peak_in_use(), 6);
+    assert_eq!(pool.peak_maximum(), 8);
+    assert_eq!(pool.total_allocations(), 0);
+    assert_eq!(pool.total_frees(), 0);
+
+    pool.clear();
+    assert_eq!(pool.total_frees(), 6);
+    assert_eq!(pool.peak_in_use(), 6);
+}
+
+#[test]
+fn into_free_ranges() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in &[1, 2, 6] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let (maximum, free) = pool.into_free_ranges();
+    assert_eq!(maximum, 10);
+    let ranges: Vec<_> = free.free_ranges().map(|r| (r.min, r.max)).collect();
+    assert_eq!(ranges, vec![(1, 2), (6, 6)]);
+}
+
+#[test]
+fn clone_windowed() {
+    let mut pool = IndexPool::new();
+    for _ in 0..30 {
+        pool.new_id();
+    }
+    for id in (0..30).filter(|id| id % 4 == 0) {
+        pool.return_id(id).unwrap();
+    }
+
+    let window = pool.clone_windowed(6, 19);
+    let expected: Vec<_> = pool
+        .all_indices()
+        .filter(|id| (6..19).contains(id))
+        .collect();
+    assert!(window.all_indices().eq(expected.iter().cloned()));
+    assert_eq!(window.in_use(), expected.len());
+    assert_eq!(window.maximum(), 19);
+    assert_eq!(pool.in_use(), 22);
+
+    assert_eq!(pool.clone_windowed(40, 50).in_use(), 0);
+    assert_eq!(pool.clone_windowed(4, 5).maximum(), 0);
+
+    // Empty windows, including ones inside a used run
+    for &lo in &[4, 5, 6, 30, 40] {
+        let window = pool.clone_windowed(lo, lo);
+        assert_eq!(window.maximum(), 0, "window at {}", lo);
+        assert_eq!(window.in_use(), 0, "window at {}", lo);
+    }
+}
+
+#[test]
+fn same_free_set() {
+    let mut a = IndexPool::new();
+    for _ in 0..8 {
+        a.new_id();
+    }
+    a.return_id(2).unwrap();
+    a.return_id(3).unwrap();
+    a.return_id(7).unwrap();
+
+    let mut b = IndexPool::new();
+    for &id in &[6, 0, 5, 1, 4] {
+        b.request_id(id).unwrap();
+    }
+    assert!(a.same_free_set(&b));
+    assert!(b.same_free_set(&a));
+
+    b.return_id(4).unwrap();
+    assert!(!a.same_free_set(&b));
+    b.request_id(4).unwrap();
+    b.request_id(7).unwrap();
+    assert!(!a.same_free_set(&b));
+}
+
+#[test]
+fn reuse_ratio() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.reuse_ratio(), 0.0);
+
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(1).unwrap();
+    pool.return_id(3).unwrap();
+    assert_eq!(pool.new_id(), 1);
+    assert_eq!(pool.new_id(), 3);
+
+    // 2 of the 8 allocations were recycled
+    assert!((pool.reuse_ratio() - 0.25).abs() < 1e-9);
+
+    pool.reset_statistics();
+    assert_eq!(pool.reuse_ratio(), 0.0);
+}
+
+#[test]
+fn debug_layout() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.debug_layout(), "[0+ free tail]");
+
+    for _ in 0..11 {
+        pool.new_id();
+    }
+    pool.return_id(5).unwrap();
+    pool.return_id(6).unwrap();
+    pool.return_id(8).unwrap();
+    assert_eq!(
+        pool.debug_layout(),
+        "[0-4 used][5-6 free][7 used][8 free][9-10 used][11+ free tail]"
+    );
+
+    let mut bounded = IndexPool::with_limit(4);
+    bounded.request_id(1).unwrap();
+    assert_eq!(bounded.debug_layout(), "[0 free][1 used][2-3 free tail]");
+    bounded.request_id(3).unwrap();
+    assert_eq!(bounded.debug_layout(), "[0 free][1 used][2 free][3 used]");
+}
+
+#[test]
+fn used_ranges() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.used_ranges().count(), 0);
+
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in &[0, 3, 4, 7] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let runs: Vec<_> = pool.used_ranges().map(|r| (r.min, r.max)).collect();
+    assert_eq!(runs, vec![(1, 2), (5, 6), (8, 9)]);
+}
+
+#[test]
+fn allocate_pair() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.allocate_pair(), (0, 1));
+
+    for _ in 0..4 {
+        pool.new_id();
+    }
+    pool.return_id(3).unwrap();
+    assert_eq!(pool.allocate_pair(), (3, 6));
+
+    pool.return_id(0).unwrap();
+    pool.return_id(4).unwrap();
+    let (a, b) = pool.allocate_pair();
+    assert_ne!(a, b);
+    assert_eq!((a, b), (0, 4));
+}
+
+#[test]
+#[should_panic]
+fn allocate_pair_needs_room_for_both() {
+    let mut pool = IndexPool::with_limit(3);
+    pool.new_id();
+    pool.new_id();
+    pool.allocate_pair();
+}
+
+#[test]
+fn new_ids_compact() {
+    let mut pool = IndexPool::new();
+    match pool.new_ids_compact(4) {
+        NewIds::Contiguous(range) => assert_eq!((range.min, range.max), (0, 3)),
+        other => panic!("expected a contiguous run, got {:?}", other),
+    }
+
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+    pool.return_id(5).unwrap();
+
+    match pool.new_ids_compact(3) {
+        NewIds::Scattered(ids) => assert_eq!(ids, vec![2, 5, 10]),
+        other => panic!("expected scattered ids, got {:?}", other),
+    }
+
+    // Recycling a gap which is large enough still reports a single run
+    for id in 6..9 {
+        pool.return_id(id).unwrap();
+    }
+    match pool.new_ids_compact(3) {
+        NewIds::Contiguous(range) => assert_eq!((range.min, range.max), (6, 8)),
+        other => panic!("expected a contiguous run, got {:?}", other),
+    }
+    assert_eq!(pool.in_use(), 11);
+}
+
+#[test]
+fn is_free_range() {
+    let mut pool = IndexPool::with_limit(32);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in 2..7 {
+        pool.return_id(id).unwrap();
+    }
+
+    assert!(pool.is_free_range(Range { min: 2, max: 6 }));
+    assert!(pool.is_free_range(Range { min: 3, max: 5 }));
+    assert!(!pool.is_free_range(Range { min: 2, max: 7 }));
+    assert!(!pool.is_free_range(Range { min: 1, max: 4 }));
+    assert!(!pool.is_free_range(Range::id(9)));
+
+    // The tail is free up to the limit
+    assert!(pool.is_free_range(Range { min: 10, max: 31 }));
+    assert!(!pool.is_free_range(Range { min: 10, max: 32 }));
+}
+
+#[test]
+fn as_used_mask_u64() {
+    let mut pool = IndexPool::new();
+    for _ in 0..150 {
+        pool.new_id();
+    }
+    for id in (0..150).filter(|id| id % 3 == 0 || (60..70).contains(id)) {
+        pool.return_id(id).unwrap();
+    }
+
+    for word in 0..4 {
+        let mask = pool.as_used_mask_u64(word);
+        for bit in 0..64 {
+            let id = word * 64 + bit;
+            assert_eq!(mask & (1 << bit) != 0, !pool.is_free(id), "index {}", id);
+        }
+    }
+    assert_eq!(pool.as_used_mask_u64(usize::MAX), 0);
+
+    let mut full = IndexPool::new();
+    for _ in 0..128 {
+        full.new_id();
+    }
+    assert_eq!(full.as_used_mask_u64(1), !0);
+}
+
+#[test]
+fn overlaps() {
+    let mut pool = IndexPool::with_limit(32);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in 2..7 {
+        pool.return_id(id).unwrap();
+    }
+
+    // Grazing either edge of the [2, 6] gap touches a used index
+    assert!(pool.overlaps(Range { min: 1, max: 2 }));
+    assert!(pool.overlaps(Range { min: 6, max: 7 }));
+    assert!(!pool.overlaps(Range { min: 2, max: 6 }));
+    assert!(pool.overlaps(Range { min: 9, max: 20 }));
+    assert!(!pool.overlaps(Range { min: 10, max: 40 }));
+}
+
+#[test]
+fn used_ranges_vec() {
+    let mut pool = IndexPool::new();
+    assert!(pool.used_ranges_vec().is_empty());
+
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in &[0, 3, 4, 7] {
+        pool.return_id(*id).unwrap();
+    }
+    pool.request_id(15).unwrap();
+
+    let runs: Vec<_> = pool
+        .used_ranges_vec()
+        .iter()
+        .map(|r| (r.min, r.max))
+        .collect();
+    let expected: Vec<_> = pool.used_ranges().map(|r| (r.min, r.max)).collect();
+    assert_eq!(runs, expected);
+    assert_eq!(runs, vec![(1, 2), (5, 6), (8, 9), (15, 15)]);
+}
+
+#[test]
+fn retain_ranges() {
+    let mut pool = IndexPool::new();
+    for _ in 0..12 {
+        pool.new_id();
+    }
+    for id in &[2, 5, 9] {
+        pool.return_id(*id).unwrap();
+    }
+
+    // Runs are [0, 1], [3, 4], [6, 8], [10, 11]; drop every other one
+    let mut keep = false;
+    pool.retain_ranges(|_| {
+        keep = !keep;
+        keep
+    });
+
+    let runs: Vec<_> = pool.used_ranges().map(|r| (r.min, r.max)).collect();
+    assert_eq!(runs, vec![(0, 1), (6, 8)]);
+    assert_eq!(pool.in_use(), 5);
+    assert_eq!(pool.maximum(), 9);
+    assert!(pool.all_indices().eq(vec![0, 1, 6, 7, 8]));
+}
+
+#[test]
+fn reserve_lowest_reuses_lowest_hole() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.reserve_lowest();
+    }
+    pool.return_id(4).unwrap();
+    pool.return_id(1).unwrap();
+
+    assert_eq!(pool.reserve_lowest(), 1);
+    assert_eq!(pool.reserve_lowest(), 4);
+    assert_eq!(pool.reserve_lowest(), 6);
+}
+
+#[test]
+fn clone_from_reuses_pool() {
+    let mut source = IndexPool::new();
+    let mut scratch = IndexPool::new();
+    for _ in 0..20 {
+        scratch.new_id();
+    }
+    scratch.return_id(3).unwrap();
+
+    for round in 0..5 {
+        source.new_id();
+        source.new_id();
+        source.return_id(round).unwrap();
+
+        scratch.clone_from(&source);
+        assert_eq!(scratch.maximum(), source.maximum());
+        assert_eq!(scratch.in_use(), source.in_use());
+        assert!(scratch.all_indices().eq(source.all_indices()));
+
+        let copy = source.clone();
+        assert!(copy.all_indices().eq(source.all_indices()));
+    }
+}
+
+#[test]
+fn index_iter_remaining() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(0).unwrap();
+    pool.return_id(3).unwrap();
+
+    let mut iter = pool.all_indices();
+    assert_eq!(iter.remaining(), 1..6);
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.remaining(), 2..6);
+    assert_eq!(iter.next(), Some(2));
+    assert_eq!(iter.remaining(), 4..6);
+    assert_eq!(iter.clone().count(), 2);
+    assert_eq!(iter.remaining(), 4..6);
+    iter.by_ref().for_each(drop);
+    assert_eq!(iter.remaining(), 6..6);
+}
+
+#[test]
+fn all_indices_bounded() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+    pool.request_id(1000).unwrap();
+
+    assert!(pool.all_indices_bounded(64).eq(vec![0, 1, 3, 4, 5]));
+    assert!(pool.all_indices_bounded(4).eq(vec![0, 1, 3]));
+    assert!(pool.all_indices_bounded(2000).eq(pool.all_indices()));
+
+    // A bound inside a free range must not run past it
+    assert!(pool.all_indices_bounded(3).eq(vec![0, 1]));
+    assert!(pool.all_indices_bounded(500).eq(vec![0, 1, 3, 4, 5]));
+    assert_eq!(pool.all_indices_bounded(0).count(), 0);
+}
+
+#[test]
+fn index_iter_peek_next() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(0).unwrap();
+    pool.return_id(3).unwrap();
+
+    let mut iter = pool.all_indices();
+    loop {
+        let peeked = iter.peek_next();
+        assert_eq!(peeked, iter.next());
+        if peeked.is_none() {
+            break;
+        }
+    }
+    assert_eq!(IndexPool::new().all_indices().peek_next(), None);
+}
+
+#[test]
+fn normalize_keeps_indices() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..16 {
+        pool.new_id();
+    }
+    assert_eq!(pool.request_id(30), Ok(()));
+    // Freeing the top run leaves [14, 30] free at the end of the pool
+    for id in &[1, 2, 3, 7, 9, 10, 30, 15, 14] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let before: Vec<usize> = pool.all_indices().collect();
+    assert_eq!(pool.stats().free_ranges_count, 4);
+    assert_eq!(pool.maximum(), 31);
+
+    pool.normalize();
+
+    assert!(pool.all_indices().eq(before));
+    assert_eq!(pool.stats().free_ranges_count, 3);
+    assert_eq!(pool.maximum(), 14);
+    assert_eq!(pool.in_use(), 8);
+}
+
+#[test]
+fn free_ranges_within() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in &[2, 3, 6] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let ranges: Vec<_> = pool
+        .free_ranges_within(16)
+        .map(|r| (r.min, r.max))
+        .collect();
+    assert_eq!(ranges, vec![(2, 3), (6, 6), (10, 15)]);
+
+    let ranges: Vec<_> = pool.free_ranges_within(3).map(|r| (r.min, r.max)).collect();
+    assert_eq!(ranges, vec![(2, 2)]);
+
+    let ranges: Vec<_> = pool
+        .free_ranges_within(10)
+        .map(|r| (r.min, r.max))
+        .collect();
+    assert_eq!(ranges, vec![(2, 3), (6, 6)]);
+
+    assert_eq!(pool.free_ranges_within(0).count(), 0);
+}
+
+#[test]
+fn return_if_used() {
+    let mut pool = IndexPool::new();
+    let a = pool.new_id();
+    let b = pool.new_id();
+
+    assert!(pool.return_if_used(a));
+    assert!(!pool.return_if_used(a));
+    assert!(!pool.return_if_used(100));
+    assert_eq!(pool.in_use(), 1);
+    assert!(!pool.is_free(b));
+}
+
+#[test]
+fn request_if_free() {
+    let mut pool = IndexPool::new();
+
+    assert!(pool.request_if_free(3));
+    assert!(!pool.request_if_free(3));
+    assert_eq!(pool.in_use(), 1);
+    assert!(!pool.is_free(3));
+
+    let mut bounded = IndexPool::with_limit(2);
+    assert!(!bounded.request_if_free(2));
+    assert!(bounded.request_if_free(1));
+}
+
+#[test]
+fn request_id_tracked() {
+    let mut pool = IndexPool::new();
+    pool.new_id();
+    pool.new_id();
+
+    assert_eq!(pool.request_id_tracked(2), Ok(0));
+    assert_eq!(pool.request_id_tracked(100), Ok(97));
+    assert_eq!(pool.free_ranges_count(), 1);
+    assert!(pool.is_free(3) && pool.is_free(99));
+
+    // Filling part of the gap doesn't create another one
+    assert_eq!(pool.request_id_tracked(50), Ok(0));
+    assert_eq!(pool.request_id_tracked(50), Err(index_pool::AlreadyInUse));
+    assert_eq!(pool.in_use(), 5);
+}
+
+#[test]
+fn request_id_filling() {
+    let mut pool = IndexPool::new();
+    pool.new_id();
+    pool.new_id();
+
+    let gap: Vec<_> = pool.request_id_filling(6).unwrap().collect();
+    assert_eq!(gap, vec![2, 3, 4, 5]);
+    for id in gap {
+        assert!(pool.is_free(id));
+    }
+
+    assert_eq!(pool.request_id_filling(7).unwrap().count(), 0);
+    assert_eq!(pool.request_id_filling(4).unwrap().count(), 0);
+    assert!(pool.request_id_filling(4).is_err());
+}
+
+#[test]
+fn from_sorted_used() {
+    let ids = [1, 2, 3, 7, 8, 20, 21, 40];
+    let pool = IndexPool::from_sorted_used(&ids);
+
+    let mut expected = IndexPool::new();
+    for &id in &ids {
+        expected.request_id(id).unwrap();
+    }
+
+    assert!(pool.all_indices().eq(ids.iter().cloned()));
+    assert_eq!(pool.in_use(), expected.in_use());
+    assert_eq!(pool.maximum(), expected.maximum());
+    assert!(pool
+        .used_ranges()
+        .map(|r| (r.min, r.max))
+        .eq(expected.used_ranges().map(|r| (r.min, r.max))));
+    assert_eq!(pool.free_ranges_count(), expected.free_ranges_count());
+
+    let empty = IndexPool::from_sorted_used(&[]);
+    assert_eq!(empty.in_use(), 0);
+    assert_eq!(empty.maximum(), 0);
+}
+
+#[test]
+#[should_panic]
+fn from_sorted_used_rejects_usize_max() {
+    IndexPool::from_sorted_used(&[3, usize::MAX]);
+}
+
+#[test]
+fn reserve_specific_or_next() {
+    let mut pool = IndexPool::new();
+    pool.request_id(1).unwrap();
+    pool.request_id(4).unwrap();
+
+    let assigned = pool.reserve_specific_or_next(&[4, 7, 1, 7, 2]);
+    assert_eq!(assigned, vec![0, 7, 2, 3, 5]);
+    assert_eq!(pool.in_use(), 7);
+    for &id in &assigned {
+        assert!(!pool.is_free(id));
+    }
+
+    let mut bounded = IndexPool::with_limit(4);
+    assert_eq!(bounded.reserve_specific_or_next(&[10, 2]), vec![0, 2]);
+}
+
+#[test]
+fn reserve_from_retries_collisions() {
+    let mut pool = IndexPool::new();
+    pool.request_id(7).unwrap();
+    pool.request_id(3).unwrap();
+
+    let mut candidates = vec![7, 3, 12, 5].into_iter();
+    let mut calls = 0;
+    let id = pool.reserve_from(|| {
+        calls += 1;
+        candidates.next().unwrap()
+    });
+    assert_eq!(id, 12);
+    assert_eq!(calls, 3);
+    assert!(!pool.is_free(12));
+
+    assert_eq!(pool.try_reserve_from(4, || 7), None);
+    let mut next = 2;
+    assert_eq!(
+        pool.try_reserve_from(4, || {
+            next += 1;
+            next
+        }),
+        Some(4)
+    );
+}
+
+#[test]
+fn relocate() {
+    use index_pool::RelocateError;
+
+    let mut pool = IndexPool::with_limit(64);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+
+    assert_eq!(pool.relocate(9, 2), Ok(()));
+    assert!(!pool.is_free(2));
+    assert!(pool.is_free(9));
+    assert_eq!(pool.maximum(), 9);
+    assert_eq!(pool.in_use(), 9);
+
+    assert_eq!(pool.relocate(9, 20), Err(RelocateError::NotInUse));
+    assert_eq!(pool.relocate(3, 4), Err(RelocateError::AlreadyInUse));
+    assert_eq!(pool.relocate(3, 64), Err(RelocateError::OutOfBounds));
+    assert!(!pool.is_free(3));
+
+    assert_eq!(pool.relocate(0, 20), Ok(()));
+    assert!(pool.all_indices().eq((1..9).chain(Some(20))));
+}
+
+#[test]
+fn request_sparse_matches_individual_requests() {
+    let mut batched = IndexPool::new();
+    let mut single = IndexPool::new();
+    for pool in &mut [&mut batched, &mut single] {
+        for _ in 0..10 {
+            pool.new_id();
+        }
+        for id in &[2, 3, 4, 7] {
+            pool.return_id(*id).unwrap();
+        }
+    }
+
+    let ids = [101, 3, 7, 100, 15];
+    assert_eq!(batched.request_sparse(&ids), Ok(()));
+    for &id in &ids {
+        assert_eq!(single.request_id(id), Ok(()));
+    }
+
+    assert_eq!(batched.maximum(), single.maximum());
+    assert_eq!(batched.in_use(), single.in_use());
+    assert!(batched.all_indices().eq(single.all_indices()));
+    assert!(batched
+        .used_ranges()
+        .map(|r| (r.min, r.max))
+        .eq(single.used_ranges().map(|r| (r.min, r.max))));
+}
+
+#[test]
+fn request_sparse_is_all_or_nothing() {
+    let mut pool = IndexPool::new();
+    pool.new_id();
+    pool.new_id();
+
+    assert_eq!(
+        pool.request_sparse(&[5, 1, 6]),
+        Err(index_pool::AlreadyInUse)
+    );
+    assert_eq!(
+        pool.request_sparse(&[5, 6, 5]),
+        Err(index_pool::AlreadyInUse)
+    );
+    assert_eq!(pool.maximum(), 2);
+    assert_eq!(pool.in_use(), 2);
+    assert!(pool.is_free(5));
+}
+
+#[test]
+fn debug_is_bounded() {
+    let mut pool = IndexPool::new();
+    for _ in 0..2000 {
+        pool.new_id();
+    }
+    for id in (0..2000).step_by(2) {
+        pool.return_id(id).unwrap();
+    }
+
+    let short = format!("{:?}", pool);
+    assert!(short.len() < 200, "{}", short);
+    assert!(short.contains("in_use: 1000"));
+    assert!(short.contains("...992 more"));
+
+    let full = format!("{:#?}", pool);
+    assert!(full.contains("(1998...1998)"));
+    assert!(!full.contains("more"));
+
+    let empty = format!("{:?}", IndexPool::with_limit(4));
+    assert_eq!(
+        empty,
+        "IndexPool { next_id: 0, in_use: 0, limit: 4, free_list: [] }"
+    );
+}
+
+#[test]
+fn new_id_by_prefers_odd() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in &[2, 4, 5, 7, 8] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let prefer_odd = |a: usize, b: usize| (a % 2).cmp(&(b % 2)).reverse().then(a.cmp(&b));
+
+    assert_eq!(pool.new_id_by(prefer_odd), 5);
+    assert_eq!(pool.new_id_by(prefer_odd), 7);
+    assert_eq!(pool.new_id_by(prefer_odd), 2);
+    assert_eq!(pool.new_id_by(prefer_odd), 4);
+    assert_eq!(pool.new_id_by(prefer_odd), 8);
+    assert_eq!(pool.in_use(), 10);
+
+    // Nothing left to recycle, so it falls back to the tail
+    assert_eq!(pool.new_id_by(prefer_odd), 10);
+}
+
+#[test]
+fn free_ranges_count() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.free_ranges_count(), 0);
+
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.return_id(1).unwrap();
+    pool.return_id(5).unwrap();
+    assert_eq!(pool.free_ranges_count(), 2);
+
+    pool.return_id(2).unwrap();
+    assert_eq!(pool.free_ranges_count(), 2);
+    assert_eq!(pool.free_ranges_count(), pool.stats().free_ranges_count);
+}
+
+#[test]
+fn with_new_id() {
+    let mut pool = IndexPool::new();
+    let mut data = Vec::new();
+
+    let id = pool.with_new_id(|id| {
+        data.push("apple");
+        id
+    });
+    assert_eq!(id, 0);
+    assert!(!pool.is_free(0));
+    assert_eq!(data[id], "apple");
+}
+
+#[test]
+fn with_new_id_unwind() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut pool = IndexPool::new();
+    pool.new_id();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.with_new_id(|_| -> () { panic!("initialization failed") });
+    }));
+    assert!(result.is_err());
+
+    assert_eq!(pool.in_use(), 1);
+    assert_eq!(pool.maximum(), 1);
+    assert!(pool.is_free(1));
+}
+
+#[test]
+fn with_request_id() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.with_request_id(3, || "apple"), Ok("apple"));
+    assert!(!pool.is_free(3));
+
+    let mut ran = false;
+    assert!(pool.with_request_id(3, || ran = true).is_err());
+    assert!(!ran);
+}
+
+#[test]
+fn with_request_id_unwind() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut pool = IndexPool::new();
+    pool.new_id();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _ = pool.with_request_id(5, || -> () { panic!("initialization failed") });
+    }));
+    assert!(result.is_err());
+
+    assert!(pool.is_free(5));
+    assert_eq!(pool.in_use(), 1);
+    assert_eq!(pool.maximum(), 1);
+
+    // Without auto-collapse the gap before the index is rolled back too
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _ = pool.with_request_id(10, || -> () { panic!("initialization failed") });
+    }));
+    assert!(result.is_err());
+    assert_eq!(pool.maximum(), 0);
+    assert_eq!(pool.free_ranges_count(), 0);
+}
+
+#[test]
+fn return_all_above() {
+    let mut pool = IndexPool::new();
+    for _ in 0..12 {
+        pool.new_id();
+    }
+    for id in &[2, 5, 6, 9] {
+        pool.return_id(*id).unwrap();
+    }
+
+    assert_eq!(pool.return_all_above(6), vec![7, 8, 10, 11]);
+    assert!(pool.all_indices().eq(vec![0, 1, 3, 4]));
+    assert_eq!(pool.maximum(), 5);
+    assert_eq!(pool.in_use(), 4);
+
+    assert_eq!(pool.return_all_above(20), Vec::<usize>::new());
+    assert_eq!(pool.return_all_above(0), vec![0, 1, 3, 4]);
+    assert_eq!(pool.maximum(), 0);
+    assert_eq!(pool.in_use(), 0);
+}
+
+#[test]
+fn initial_index_counts_as_in_use() {
+    let mut pool = IndexPool::with_initial_index(4);
+    assert_eq!(pool.in_use(), 4);
+    assert!(pool.all_indices().eq(0..4));
+
+    assert_eq!(pool.new_id(), 4);
+    assert_eq!(pool.in_use(), 5);
+
+    pool.return_id(1).unwrap();
+    assert_eq!(pool.in_use(), 4);
+    assert_eq!(pool.new_id(), 1);
+    assert_eq!(pool.new_id(), 5);
+}
+
+#[test]
+fn in_use_excluding_prefix() {
+    let mut pool = IndexPool::with_initial_index(10);
+    assert_eq!(pool.prefix_len(), 10);
+    assert_eq!(pool.in_use(), 10);
+    assert_eq!(pool.in_use_excluding_prefix(), 0);
+
+    pool.new_id();
+    pool.new_id();
+    pool.new_id();
+    assert_eq!(pool.in_use(), 13);
+    assert_eq!(pool.in_use_excluding_prefix(), 3);
+
+    // The prefix never counts, whether or not it's in use
+    pool.return_id(2).unwrap();
+    pool.return_id(3).unwrap();
+    assert_eq!(pool.in_use(), 11);
+    assert_eq!(pool.in_use_excluding_prefix(), 3);
+    assert_eq!(pool.new_id(), 2);
+    assert_eq!(pool.in_use_excluding_prefix(), 3);
+
+    assert_eq!(IndexPool::new().prefix_len(), 0);
+
+    // Returning the end of the prefix shrinks the pool below it
+    let mut pool = IndexPool::with_initial_index(3);
+    pool.return_id(2).unwrap();
+    assert_eq!(pool.maximum(), 2);
+    assert_eq!(pool.in_use_excluding_prefix(), 0);
+}
+
+#[test]
+fn reserve_capacity() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.capacity(), 0);
+
+    pool.reserve(64);
+    assert_eq!(pool.capacity(), 64);
+    assert_eq!(pool.maximum(), 0);
+    assert!(pool.all_indices().eq(None));
+
+    assert_eq!(pool.new_id(), 0);
+    assert_eq!(pool.new_id(), 1);
+    assert_eq!(pool.maximum(), 2);
+    assert_eq!(pool.capacity(), 64);
+
+    // Reserving less never shrinks the capacity
+    pool.reserve(8);
+    assert_eq!(pool.capacity(), 64);
+
+    pool.request_id(99).unwrap();
+    assert_eq!(pool.capacity(), 100);
+
+    let mut bounded = IndexPool::with_limit(16);
+    bounded.reserve(100);
+    assert_eq!(bounded.capacity(), 16);
+}
+
+#[test]
+fn indices_near_usize_max() {
+    let mut pool = IndexPool::new();
+    assert!(pool.return_id(usize::MAX).is_err());
+    assert!(pool.free_block_slot(usize::MAX, 2).is_err());
+
+    pool.request_id(usize::MAX - 1).unwrap();
+    assert_eq!(pool.maximum(), usize::MAX);
+    assert!(pool.try_new_id().is_ok());
+    assert!(pool.free_block_slot(usize::MAX - 1, 2).is_err());
+    assert!(pool.return_id(usize::MAX - 1).is_ok());
+}
+
+#[test]
+fn take_used_in_chunks() {
+    let mut pool = IndexPool::new();
+    for _ in 0..20 {
+        pool.new_id();
+    }
+    for &id in &[0, 3, 4, 9, 15] {
+        pool.return_id(id).unwrap();
+    }
+
+    let mut taken = Vec::new();
+    loop {
+        let chunk = pool.take_used(4);
+        assert!(chunk.len() <= 4);
+        if chunk.is_empty() {
+            break;
+        }
+        taken.extend(chunk);
+    }
+    assert!(taken.iter().cloned().eq(pool.all_indices()));
+
+    // The next pass starts over
+    assert_eq!(pool.take_used(2), vec![1, 2]);
+    pool.reset_take_used();
+    assert_eq!(pool.take_used(3), vec![1, 2, 5]);
+}
+
+#[test]
+fn contains_all_ids() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+
+    assert!(pool.contains_all(vec![0, 1, 3, 5]));
+    assert!(pool.contains_all(Vec::new()));
+    assert!(!pool.contains_all(vec![0, 2, 3]));
+    assert!(!pool.contains_all(vec![1, 6]));
+    assert!(!pool.contains_all(0..10));
+}
+
+#[test]
+fn range_invariants_hold() {
+    let mut pool = IndexPool::new();
+    assert!(pool.check_range_invariants());
+    for _ in 0..12 {
+        pool.new_id();
+    }
+    for &id in &[1, 2, 5, 3, 8] {
+        pool.return_id(id).unwrap();
+        pool.assert_ranges_sorted();
+    }
+    pool.request_id(20).unwrap();
+    assert!(pool.check_range_invariants());
+    pool.free_block_slot(9, 3).unwrap();
+    assert!(pool.check_range_invariants());
+}
+
+#[test]
+fn pop_lowest_in_order() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.pop_lowest_used(), None);
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    pool.return_id(0).unwrap();
+    pool.return_id(4).unwrap();
+
+    let popped: Vec<usize> = (0..6).filter_map(|_| pool.pop_lowest_used()).collect();
+    assert_eq!(popped, vec![1, 2, 3, 5, 6, 7]);
+    assert_eq!(pool.pop_lowest_used(), None);
+    assert_eq!(pool.in_use(), 0);
+    assert_eq!(pool.maximum(), 0);
+}
+
+#[test]
+fn pop_highest_in_order() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.pop_highest_used(), None);
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    pool.return_id(3).unwrap();
+
+    for &id in &[7, 6, 5] {
+        assert_eq!(pool.pop_highest_used(), Some(id));
+        assert_eq!(pool.maximum(), id);
+    }
+    // Collapsing swallows the hole at 3 too
+    assert_eq!(pool.pop_highest_used(), Some(4));
+    assert_eq!(pool.maximum(), 3);
+    assert_eq!(pool.pop_highest_used(), Some(2));
+    assert_eq!(pool.maximum(), 2);
+    assert_eq!(pool.pop_highest_used(), Some(1));
+    assert_eq!(pool.pop_highest_used(), Some(0));
+    assert_eq!(pool.pop_highest_used(), None);
+}
+
+#[test]
+fn pop_highest_without_auto_collapse() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(5).unwrap();
+    pool.return_id(4).unwrap();
+
+    assert_eq!(pool.pop_highest_used(), Some(3));
+    assert_eq!(pool.pop_highest_used(), Some(2));
+    assert_eq!(pool.in_use(), 2);
+    assert_eq!(pool.maximum(), 6);
+}
+
+#[test]
+fn ids_in_use_snapshot() {
+    let mut pool = IndexPool::with_initial_index(2);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for &id in &[3, 7, 8] {
+        pool.return_id(id).unwrap();
+    }
+
+    let ids = pool.ids_in_use();
+    assert_eq!(ids.len(), pool.in_use());
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(ids, vec![0, 1, 2, 4, 5, 6, 9, 10, 11]);
+}
+
+#[test]
+fn request_or_suggest_nearest() {
+    let mut pool = IndexPool::new();
+    for _ in 0..20 {
+        pool.new_id();
+    }
+    for &id in &[4, 9, 10] {
+        pool.return_id(id).unwrap();
+    }
+
+    assert_eq!(pool.request_id_or_nearest(10), Ok(()));
+    assert_eq!(pool.request_id_or_nearest(7), Err(9));
+    assert_eq!(pool.request_id_or_nearest(6), Err(4));
+    // 6 is now as far from 4 as from 8, and ties go to the lower index
+    pool.return_id(8).unwrap();
+    assert_eq!(pool.request_id_or_nearest(6), Err(4));
+    assert_eq!(pool.request_id_or_nearest(2), Err(4));
+    // Past the last gap, the tail is closest
+    assert_eq!(pool.request_id_or_nearest(18), Err(20));
+    assert!(!pool.is_free(7) && pool.is_free(4));
+}
+
+#[test]
+fn free_ranges_both_ways() {
+    let mut pool = IndexPool::new();
+    for _ in 0..12 {
+        pool.new_id();
+    }
+    for &id in &[1, 2, 5, 8, 9, 10] {
+        pool.return_id(id).unwrap();
+    }
+
+    let forward: Vec<(usize, usize)> = pool.free_ranges().map(|r| (r.min, r.max)).collect();
+    let mut backward: Vec<(usize, usize)> =
+        pool.free_ranges().rev().map(|r| (r.min, r.max)).collect();
+    assert_eq!(forward, vec![(1, 2), (5, 5), (8, 10)]);
+    backward.reverse();
+    assert_eq!(forward, backward);
+    assert_eq!(pool.free_ranges().len(), pool.free_ranges_count());
+}
+
+#[test]
+fn rebase_shifts_every_index() {
+    let mut pool = IndexPool::with_initial_index(2);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for &id in &[3, 6, 7] {
+        pool.return_id(id).unwrap();
+    }
+    let before = pool.ids_in_use();
+    let free_before: Vec<(usize, usize)> = pool.free_ranges().map(|r| (r.min, r.max)).collect();
+
+    let map = pool.rebase(100);
+    assert_eq!(map.offset(), 98);
+    assert_eq!(pool.prefix_len(), 100);
+    assert_eq!(pool.maximum(), 110);
+    assert_eq!(pool.in_use_excluding_prefix(), 7);
+
+    for &id in &before {
+        assert!(!pool.is_free(map.map(id)));
+    }
+    let free_after: Vec<(usize, usize)> = pool
+        .free_ranges()
+        .map(|r| (r.min - map.offset(), r.max - map.offset()))
+        .collect();
+    assert_eq!(free_before, free_after);
+    assert!(pool.contains_all(0..100));
+    assert_eq!(pool.new_id(), map.map(3));
+}
+
+#[test]
+fn contiguous_check() {
+    let mut pool = IndexPool::new();
+    assert!(pool.is_contiguous());
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    assert!(pool.is_contiguous());
+
+    pool.return_id(3).unwrap();
+    assert!(!pool.is_contiguous());
+
+    // Moving the top index into the hole packs the pool again
+    pool.relocate(7, 3).unwrap();
+    assert!(pool.is_contiguous());
+    assert_eq!(pool.maximum(), 7);
+}
+
+#[test]
+fn request_sorted_matches_individual_requests() {
+    let mut batched = IndexPool::new();
+    let mut single = IndexPool::new();
+    for pool in &mut [&mut batched, &mut single] {
+        for _ in 0..20 {
+            pool.new_id();
+        }
+        for id in (2..5).chain(7..12).chain(Some(15)) {
+            pool.return_id(id).unwrap();
+        }
+    }
+
+    let ids = [2, 4, 8, 9, 11, 15, 20, 21, 30];
+    assert_eq!(batched.request_sorted(&ids), Ok(()));
+    for &id in &ids {
+        assert_eq!(single.request_id(id), Ok(()));
+    }
+
+    assert_eq!(batched.maximum(), single.maximum());
+    assert_eq!(batched.in_use(), single.in_use());
+    assert!(batched.check_range_invariants());
+    assert!(batched.same_free_set(&single));
+    assert!(batched.all_indices().eq(single.all_indices()));
+}
+
+#[test]
+fn request_sorted_is_all_or_nothing() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+
+    assert_eq!(
+        pool.request_sorted(&[2, 3, 8]),
+        Err(index_pool::AlreadyInUse)
+    );
+    assert_eq!(pool.request_sorted(&[1]), Err(index_pool::AlreadyInUse));
+    assert_eq!(pool.maximum(), 6);
+    assert_eq!(pool.in_use(), 5);
+    assert!(pool.is_free(2));
+
+    assert_eq!(pool.request_sorted(&[]), Ok(()));
+    assert_eq!(pool.request_sorted(&[2, 8]), Ok(()));
+    assert_eq!(pool.in_use(), 7);
+    assert!(pool.all_indices().eq((0..6).chain(Some(8))));
+}
+
+#[test]
+fn from_option_slice() {
+    let data = [
+        Some('a'),
+        None,
+        None,
+        Some('b'),
+        Some('c'),
+        None,
+        Some('d'),
+        None,
+        None,
+    ];
+    let mut pool = IndexPool::from_option_slice(&data);
+
+    assert_eq!(pool.maximum(), 7);
+    assert_eq!(pool.in_use(), 4);
+    for (id, slot) in data.iter().enumerate() {
+        assert_eq!(pool.is_free(id), slot.is_none(), "index {}", id);
+    }
+    assert!(pool.check_range_invariants());
+    assert_eq!(pool.new_id(), 1);
+
+    let empty = IndexPool::from_option_slice::<u8>(&[None, None]);
+    assert_eq!(empty.maximum(), 0);
+    assert_eq!(empty.in_use(), 0);
+}
+
+#[test]
+fn matches_option_slice() {
+    let mut data = vec![Some(0), None, Some(2), Some(3), None, None];
+    let mut pool = IndexPool::from_option_slice(&data);
+    assert!(pool.matches_option_slice(&data));
+
+    data[1] = Some(1);
+    assert!(!pool.matches_option_slice(&data));
+    pool.request_id(1).unwrap();
+    assert!(pool.matches_option_slice(&data));
+
+    data[3] = None;
+    assert!(!pool.matches_option_slice(&data));
+    pool.return_id(3).unwrap();
+    assert!(pool.matches_option_slice(&data));
+
+    // The storage is too short to hold every index in use
+    pool.request_id(8).unwrap();
+    assert!(!pool.matches_option_slice(&data));
+}
+
+#[test]
+fn allocate_n_policies() {
+    let mut fragmented = IndexPool::new();
+    for _ in 0..20 {
+        fragmented.new_id();
+    }
+    // Gaps of 1, 2 and 4
+    for id in Some(1).into_iter().chain(4..6).chain(10..14) {
+        fragmented.return_id(id).unwrap();
+    }
+
+    let mut pool = fragmented.clone();
+    assert_eq!(pool.allocate_n(3, true), vec![10, 11, 12]);
+    assert_eq!(pool.allocate_n(5, true), vec![20, 21, 22, 23, 24]);
+    assert_eq!(pool.allocate_n(0, true), Vec::<usize>::new());
+
+    let mut pool = fragmented.clone();
+    assert_eq!(pool.allocate_n(3, false), vec![1, 4, 5]);
+    assert_eq!(pool.allocate_n(6, false), vec![10, 11, 12, 13, 20, 21]);
+}
+
+#[test]
+fn allocate_n_scatters_at_the_limit() {
+    let mut pool = IndexPool::with_limit(8);
+    for _ in 0..8 {
+        pool.new_id();
+    }
+    for &id in &[1, 3, 6] {
+        pool.return_id(id).unwrap();
+    }
+    assert_eq!(pool.allocate_n(3, true), vec![1, 3, 6]);
+    assert_eq!(pool.in_use(), 8);
+}
+
+#[test]
+fn gap_sizes_in_order() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.gap_sizes().count(), 0);
+    for _ in 0..20 {
+        pool.new_id();
+    }
+    for id in (2..5).chain(Some(8)).chain(12..17) {
+        pool.return_id(id).unwrap();
+    }
+    assert!(pool.gap_sizes().eq(vec![3, 1, 5]));
+}
+
+#[test]
+fn free_capacity_within_bound() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.free_capacity_within(0), 0);
+    assert_eq!(pool.free_capacity_within(10), 10);
+    for _ in 0..16 {
+        pool.new_id();
+    }
+    for id in (2..5).chain(Some(8)).chain(11..14) {
+        pool.return_id(id).unwrap();
+    }
+
+    for &upper in &[0, 3, 9, 12, 16, 40] {
+        let expected = (0..upper).filter(|&id| pool.is_free(id)).count();
+        assert_eq!(
+            pool.free_capacity_within(upper),
+            expected,
+            "upper {}",
+            upper
+        );
+    }
+}
+
+#[test]
+fn try_scope_reverts_on_none() {
+    let mut pool = IndexPool::new();
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+    let before = pool.clone();
+
+    let result: Option<()> = pool.try_scope(|pool| {
+        pool.new_id();
+        pool.request_id(10).unwrap();
+        pool.return_id(0).unwrap();
+        None
+    });
+    assert_eq!(result, None);
+    assert_eq!(pool.maximum(), before.maximum());
+    assert_eq!(pool.in_use(), before.in_use());
+    assert_eq!(pool.total_allocations(), before.total_allocations());
+    assert!(pool.same_free_set(&before));
+
+    let result = pool.try_scope(|pool| {
+        let id = pool.new_id();
+        pool.request_id(10).unwrap();
+        Some(id)
+    });
+    assert_eq!(result, Some(2));
+    assert!(pool.all_indices().eq((0..6).chain(Some(10))));
+}
+
+#[test]
+fn try_scope_inside_batch_keeps_layout() {
+    let mut pool = IndexPool::new();
+    pool.batch(|pool| {
+        for _ in 0..10 {
+            pool.new_id();
+        }
+        pool.return_id(8).unwrap();
+        pool.return_id(9).unwrap();
+        let layout = pool.debug_layout();
+
+        let result: Option<()> = pool.try_scope(|pool| {
+            pool.new_id();
+            None
+        });
+        assert_eq!(result, None);
+        assert_eq!(pool.maximum(), 10);
+        assert_eq!(pool.debug_layout(), layout);
+    });
+    assert_eq!(pool.maximum(), 8);
+}
+
+#[test]
+fn mutate_data_of_live_ids() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for &id in &[0, 3, 4, 8] {
+        pool.return_id(id).unwrap();
+    }
+
+    let mut data = vec![0usize; 12];
+    for (id, slot) in pool.all_indices_mut_data(&mut data) {
+        *slot = id * 10;
+    }
+    assert_eq!(data, vec![0, 10, 20, 0, 0, 50, 60, 70, 0, 90, 0, 0]);
+
+    let empty = IndexPool::new();
+    assert_eq!(empty.all_indices_mut_data(&mut data).count(), 0);
+}
+
+#[test]
+fn return_id_rejects_unusual_input() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.return_id(0), Err(AlreadyReturned));
+    assert_eq!(pool.return_id(usize::MAX), Err(AlreadyReturned));
+
+    for _ in 0..6 {
+        pool.new_id();
+    }
+    pool.set_auto_collapse(false);
+    pool.return_id(5).unwrap();
+    pool.return_id(2).unwrap();
+
+    // The free top index and a free interior index
+    assert_eq!(pool.return_id(5), Err(AlreadyReturned));
+    assert_eq!(pool.return_id(2), Err(AlreadyReturned));
+    pool.set_auto_collapse(true);
+    assert_eq!(pool.return_id(5), Err(AlreadyReturned));
+    assert_eq!(pool.in_use(), 4);
+}
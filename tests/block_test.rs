@@ -0,0 +1,246 @@
+extern crate index_pool;
+use index_pool::{AlreadyReturned, IndexPool};
+
+#[test]
+fn allocate_and_free_blocks() {
+    let mut pool = IndexPool::new();
+
+    let a = pool.allocate_block(4);
+    let b = pool.allocate_block(3);
+    assert_eq!((a.range().min, a.range().max), (0, 3));
+    assert_eq!((b.range().min, b.range().max), (4, 6));
+    assert_eq!(a.len(), 4);
+    assert_eq!(b.start(), 4);
+    assert_eq!(pool.in_use(), 7);
+
+    pool.free_block(a).unwrap();
+    assert!((0..4).all(|id| pool.is_free(id)));
+    assert!((4..7).all(|id| !pool.is_free(id)));
+    assert_eq!(pool.in_use(), 3);
+    assert_eq!(pool.maximum(), 7);
+
+    // Reuses the gap left behind by `a`
+    let c = pool.allocate_block(2);
+    assert_eq!((c.range().min, c.range().max), (0, 1));
+
+    // Doesn't fit in the remaining [2, 3] gap, so it goes on the tail
+    let d = pool.allocate_block(3);
+    assert_eq!((d.range().min, d.range().max), (7, 9));
+
+    pool.free_block(d).unwrap();
+    pool.free_block(b).unwrap();
+    assert_eq!(pool.maximum(), 2);
+    assert!(pool.all_indices().eq(vec![0, 1]));
+}
+
+#[test]
+fn free_foreign_block() {
+    let mut pool = IndexPool::new();
+    let mut other = IndexPool::new();
+    let block = other.allocate_block(5);
+
+    assert!(pool.free_block(block).is_err());
+    assert_eq!(pool.in_use(), 0);
+}
+
+#[test]
+fn find_contiguous_aligned() {
+    let mut pool = IndexPool::new();
+    for _ in 0..20 {
+        pool.new_id();
+    }
+    // Gaps at [3, 9] and [13, 17]
+    for id in (3..10).chain(13..18) {
+        pool.return_id(id).unwrap();
+    }
+
+    let r = pool.find_contiguous_aligned(4, 1).unwrap();
+    assert_eq!((r.min, r.max), (3, 6));
+    let r = pool.find_contiguous_aligned(4, 4).unwrap();
+    assert_eq!((r.min, r.max), (4, 7));
+    let r = pool.find_contiguous_aligned(4, 8).unwrap();
+    assert_eq!((r.min, r.max), (24, 27));
+    let r = pool.find_contiguous_aligned(3, 7).unwrap();
+    assert_eq!((r.min, r.max), (7, 9));
+    let r = pool.find_contiguous_aligned(5, 1).unwrap();
+    assert_eq!((r.min, r.max), (3, 7));
+
+    // Probing doesn't allocate anything
+    assert_eq!(pool.in_use(), 8);
+    assert_eq!(pool.maximum(), 20);
+
+    let winner = pool.find_contiguous_aligned(2, 16).unwrap();
+    assert_eq!((winner.min, winner.max), (16, 17));
+    assert_eq!(pool.request_range(winner), Ok(()));
+    assert!(!pool.is_free(16));
+    assert!(!pool.is_free(17));
+    assert!(pool.is_free(15));
+    assert_eq!(pool.in_use(), 10);
+
+    assert!(pool.request_range(winner).is_err());
+    assert_eq!(pool.in_use(), 10);
+}
+
+#[test]
+fn request_range_past_tail() {
+    let mut pool = IndexPool::new();
+    pool.new_id();
+
+    let range = index_pool::Range { min: 4, max: 6 };
+    assert_eq!(pool.request_range(range), Ok(()));
+    assert_eq!(pool.maximum(), 7);
+    assert_eq!(pool.in_use(), 4);
+    assert!(pool.all_indices().eq(vec![0, 4, 5, 6]));
+
+    let mut bounded = IndexPool::with_limit(8);
+    bounded.new_id();
+    assert!(bounded.find_contiguous_aligned(4, 8).is_none());
+    let r = bounded.find_contiguous_aligned(4, 4).unwrap();
+    assert_eq!((r.min, r.max), (4, 7));
+}
+
+#[test]
+fn reserve_range_or_grow() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in 2..6 {
+        pool.return_id(id).unwrap();
+    }
+
+    // Fits in the [2, 5] gap
+    let r = pool.reserve_range_or_grow(3);
+    assert_eq!((r.min, r.max), (2, 4));
+    assert_eq!(pool.maximum(), 10);
+
+    // Too big for the remaining [5, 5] gap, so the tail grows
+    let r = pool.reserve_range_or_grow(2);
+    assert_eq!((r.min, r.max), (10, 11));
+    assert_eq!(pool.maximum(), 12);
+    assert_eq!(pool.in_use(), 11);
+    assert!(pool.is_free(5));
+}
+
+#[test]
+fn block_slots() {
+    let mut pool = IndexPool::new();
+    pool.new_id();
+
+    let a = pool.allocate_block_slot(16);
+    let b = pool.allocate_block_slot(16);
+    let c = pool.allocate_block_slot(16);
+    assert_eq!((a, b, c), (16, 32, 48));
+    assert!(pool.is_free(1) && pool.is_free(15));
+
+    pool.free_block_slot(b, 16).unwrap();
+    assert_eq!(pool.free_block_slot(b, 16), Err(AlreadyReturned));
+    assert_eq!(pool.allocate_block_slot(16), b);
+
+    for id in 1..16 {
+        pool.request_id(id).unwrap();
+    }
+    for _ in 0..4 {
+        assert_eq!(pool.allocate_block_slot(16) % 16, 0);
+    }
+    assert_eq!(pool.maximum(), 128);
+}
+
+#[test]
+fn smallest_fitting_gap() {
+    let mut pool = IndexPool::new();
+    for _ in 0..30 {
+        pool.new_id();
+    }
+    // Gaps of 3 at [2, 4], 10 at [6, 15] and 5 at [20, 24]
+    for id in (2..5).chain(6..16).chain(20..25) {
+        pool.return_id(id).unwrap();
+    }
+
+    let gap = pool.smallest_fitting_gap(4).unwrap();
+    assert_eq!((gap.min, gap.max), (20, 24));
+    let gap = pool.smallest_fitting_gap(3).unwrap();
+    assert_eq!((gap.min, gap.max), (2, 4));
+    assert!(pool.smallest_fitting_gap(11).is_none());
+    assert_eq!(pool.in_use(), 12);
+}
+
+#[test]
+fn allocate_several_blocks() {
+    let mut pool = IndexPool::new();
+    for _ in 0..40 {
+        pool.new_id();
+    }
+    // Gaps of 10 and 3, only the first of which fits a block
+    for id in (5..15).chain(20..23) {
+        pool.return_id(id).unwrap();
+    }
+
+    let starts = pool.allocate_blocks(8, 4);
+    assert_eq!(starts.len(), 4);
+    assert_eq!(starts[0], 5);
+
+    let mut sorted = starts.clone();
+    sorted.sort();
+    for pair in sorted.windows(2) {
+        assert!(pair[0] + 8 <= pair[1]);
+    }
+    for &start in &starts {
+        assert!(pool.contains_all(start..start + 8));
+    }
+    assert_eq!(pool.in_use(), 40 - 13 + 32);
+}
+
+#[test]
+fn allocate_blocks_past_limit() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut pool = IndexPool::with_limit(20);
+    pool.new_id();
+    let result = catch_unwind(AssertUnwindSafe(|| pool.allocate_blocks(8, 3)));
+    assert!(result.is_err());
+    assert_eq!(pool.in_use(), 1);
+    assert_eq!(pool.allocate_blocks(8, 2), vec![1, 9]);
+}
+
+#[test]
+fn allocate_blocks_failure_leaves_no_trace() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    let mut pool = IndexPool::with_limit(10);
+    pool.set_auto_collapse(false);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    pool.set_on_allocate(Box::new(move |id| log.lock().unwrap().push(id)));
+    let log = seen.clone();
+    pool.set_on_free(Box::new(move |id| log.lock().unwrap().push(id)));
+
+    let result = catch_unwind(AssertUnwindSafe(|| pool.allocate_blocks(4, 3)));
+    assert!(result.is_err());
+    assert_eq!(pool.in_use(), 0);
+    assert_eq!(pool.maximum(), 0);
+    assert_eq!(pool.total_allocations(), 0);
+    assert_eq!(pool.total_frees(), 0);
+    assert_eq!(pool.peak_in_use(), 0);
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[test]
+fn reserve_tail_skips_gaps() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.return_id(2).unwrap();
+    pool.return_id(5).unwrap();
+
+    let range = pool.reserve_tail(3);
+    assert_eq!((range.min, range.max), (10, 12));
+    assert_eq!(pool.maximum(), 13);
+    assert!(pool.is_free(2) && pool.is_free(5));
+    assert_eq!(pool.in_use(), 11);
+
+    let range = pool.reserve_tail(1);
+    assert_eq!((range.min, range.max), (13, 13));
+}
@@ -0,0 +1,124 @@
+extern crate index_pool;
+use index_pool::{IndexPool, NewIds};
+
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn callbacks_fire() {
+    let allocated = Arc::new(Mutex::new(Vec::new()));
+    let freed = Arc::new(Mutex::new(Vec::new()));
+
+    let mut pool = IndexPool::new();
+    let log = allocated.clone();
+    pool.set_on_allocate(Box::new(move |id| log.lock().unwrap().push(id)));
+    let log = freed.clone();
+    pool.set_on_free(Box::new(move |id| log.lock().unwrap().push(id)));
+
+    let a = pool.new_id();
+    let b = pool.new_id();
+    pool.request_id(5).unwrap();
+    pool.return_id(a).unwrap();
+    assert!(pool.return_id(a).is_err());
+    pool.return_id(b).unwrap();
+    let block = pool.allocate_block(2);
+    pool.free_block(block).unwrap();
+
+    assert_eq!(*allocated.lock().unwrap(), vec![0, 1, 5, 0, 1]);
+    assert_eq!(*freed.lock().unwrap(), vec![0, 1, 0, 1]);
+
+    pool.clear();
+    assert_eq!(*freed.lock().unwrap(), vec![0, 1, 0, 1, 5]);
+
+    pool.clear_callbacks();
+    pool.new_id();
+    assert_eq!(allocated.lock().unwrap().len(), 5);
+}
+
+#[test]
+fn clone_drops_callbacks() {
+    let count = Arc::new(Mutex::new(0));
+    let mut pool = IndexPool::new();
+    let counter = count.clone();
+    pool.set_on_allocate(Box::new(move |_| *counter.lock().unwrap() += 1));
+
+    let mut copy = pool.clone();
+    copy.new_id();
+    assert_eq!(*count.lock().unwrap(), 0);
+
+    pool.new_id();
+    assert_eq!(*count.lock().unwrap(), 1);
+}
+
+#[test]
+fn fragmentation_warning() {
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+
+    let mut pool = IndexPool::new();
+    pool.set_max_free_ranges(2);
+    let log = warnings.clone();
+    pool.set_on_fragmented(Box::new(move |count| log.lock().unwrap().push(count)));
+
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.return_id(1).unwrap();
+    pool.return_id(3).unwrap();
+    assert!(!pool.should_compact());
+    assert!(warnings.lock().unwrap().is_empty());
+
+    pool.return_id(5).unwrap();
+    assert!(pool.should_compact());
+    assert_eq!(*warnings.lock().unwrap(), vec![3]);
+
+    // Staying above the limit doesn't fire again
+    pool.return_id(7).unwrap();
+    assert_eq!(*warnings.lock().unwrap(), vec![3]);
+
+    // Dropping back below re-arms it
+    pool.request_id(7).unwrap();
+    pool.request_id(5).unwrap();
+    assert!(!pool.should_compact());
+    pool.return_id(5).unwrap();
+    assert_eq!(*warnings.lock().unwrap(), vec![3, 3]);
+}
+
+#[test]
+fn grow_callback_fires_once_per_grow() {
+    let grown = Arc::new(Mutex::new(Vec::new()));
+    let mut pool = IndexPool::new();
+    let log = grown.clone();
+    pool.set_on_grow(Box::new(move |max| log.lock().unwrap().push(max)));
+
+    pool.new_id();
+    pool.new_id();
+    pool.request_id(5).unwrap();
+    // Reusing a gap doesn't grow the pool
+    pool.request_id(3).unwrap();
+    pool.new_id();
+    pool.reserve_range_or_grow(4);
+    assert_eq!(*grown.lock().unwrap(), vec![1, 2, 6, 10]);
+
+    // After the tail collapses, growing back fires again
+    pool.return_id(9).unwrap();
+    pool.request_id(9).unwrap();
+    pool.request_sparse(&[20, 12, 30]).unwrap();
+    assert_eq!(*grown.lock().unwrap(), vec![1, 2, 6, 10, 10, 31]);
+
+    // Bulk allocations built out of single ones fire once at the end
+    pool.allocate_n(25, false);
+    assert_eq!(grown.lock().unwrap()[6..], [37]);
+    pool.return_id(4).unwrap();
+    match pool.new_ids_compact(3) {
+        NewIds::Scattered(ids) => assert_eq!(ids, vec![4, 37, 38]),
+        other => panic!("expected scattered ids, got {:?}", other),
+    }
+    assert_eq!(grown.lock().unwrap()[7..], [39]);
+    assert_eq!(pool.allocate_pair(), (39, 40));
+    assert_eq!(grown.lock().unwrap()[8..], [41]);
+    pool.reserve_specific_or_next(&[45, 46, 47]);
+    assert_eq!(grown.lock().unwrap()[9..], [48]);
+
+    pool.clear_callbacks();
+    pool.new_id();
+    assert_eq!(grown.lock().unwrap().len(), 10);
+}
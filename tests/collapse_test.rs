@@ -0,0 +1,383 @@
+extern crate index_pool;
+use index_pool::{IndexPool, Range};
+
+fn assert_empty(pool: &IndexPool) {
+    assert_eq!(pool.free_ranges_count(), 0);
+    assert_eq!(pool.maximum(), 0);
+    assert_eq!(pool.in_use(), 0);
+}
+
+#[test]
+fn free_top_down() {
+    let mut pool = IndexPool::new();
+    for _ in 0..1000 {
+        pool.new_id();
+    }
+    for id in (0..1000).rev() {
+        pool.return_id(id).unwrap();
+        assert_eq!(pool.maximum(), id);
+        assert_eq!(pool.free_ranges_count(), 0);
+    }
+    assert_empty(&pool);
+}
+
+#[test]
+fn free_bottom_up() {
+    let mut pool = IndexPool::new();
+    for _ in 0..1000 {
+        pool.new_id();
+    }
+    for id in 0..1000 {
+        pool.return_id(id).unwrap();
+    }
+    assert_empty(&pool);
+}
+
+#[test]
+fn free_interleaved() {
+    let mut pool = IndexPool::new();
+    for _ in 0..1000 {
+        pool.new_id();
+    }
+
+    // Evens first, then odds from the top down, so ranges keep merging
+    // with the tail from both sides
+    for id in (0..1000).filter(|id| id % 2 == 0) {
+        pool.return_id(id).unwrap();
+    }
+    for id in (0..1000).filter(|id| id % 2 == 1).rev() {
+        pool.return_id(id).unwrap();
+    }
+    assert_empty(&pool);
+
+    for _ in 0..1000 {
+        pool.new_id();
+    }
+    // A fixed pseudo-random permutation of [0, 1000)
+    for i in 0..1000 {
+        pool.return_id(i * 617 % 1000).unwrap();
+    }
+    assert_empty(&pool);
+}
+
+#[test]
+fn collapse_chain_across_ranges() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+
+    for &id in &[9, 8, 7] {
+        pool.return_id(id).unwrap();
+        assert_eq!(pool.maximum(), id);
+    }
+
+    // 5 is surrounded by in-use indices, so nothing collapses
+    pool.return_id(5).unwrap();
+    assert_eq!(pool.maximum(), 7);
+    assert_eq!(pool.free_ranges_count(), 1);
+
+    // Freeing 6 joins [5, 6] to the tail, but 4 is still in use
+    pool.return_id(6).unwrap();
+    assert_eq!(pool.maximum(), 5);
+    assert_eq!(pool.free_ranges_count(), 0);
+
+    for &id in &[1, 3, 0, 2] {
+        pool.return_id(id).unwrap();
+        assert_eq!(pool.maximum(), 5);
+    }
+    assert_eq!(pool.free_ranges_count(), 1);
+
+    // Freeing 4 walks the chain all the way down through [0, 3]
+    pool.return_id(4).unwrap();
+    assert_empty(&pool);
+}
+
+#[test]
+fn auto_collapse_off() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+
+    pool.return_id(9).unwrap();
+    pool.return_id(8).unwrap();
+    assert_eq!(pool.maximum(), 10);
+    assert_eq!(pool.in_use(), 8);
+    assert!(pool.is_free(9));
+    assert!(pool.all_indices().eq(0..8));
+    assert_eq!(pool.return_id(9), Err(index_pool::AlreadyReturned));
+
+    // Freed tail indices are recycled before growing the pool
+    assert_eq!(pool.new_id(), 8);
+    assert_eq!(pool.maximum(), 10);
+
+    pool.return_id(8).unwrap();
+    assert_eq!(pool.collapse_tail(), 2);
+    assert_eq!(pool.maximum(), 8);
+    assert_eq!(pool.free_ranges_count(), 0);
+
+    for id in 0..8 {
+        pool.return_id(id).unwrap();
+    }
+    assert_eq!(pool.maximum(), 8);
+    assert_eq!(pool.collapse_tail(), 8);
+    assert_empty(&pool);
+}
+
+#[test]
+fn shrink_to_floor() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..100 {
+        pool.new_id();
+    }
+    for id in 10..100 {
+        pool.return_id(id).unwrap();
+    }
+    pool.return_id(5).unwrap();
+
+    assert_eq!(pool.shrink_to(40), 60);
+    assert_eq!(pool.maximum(), 40);
+    assert!(pool.all_indices().eq((0..5).chain(6..10)));
+    assert_eq!(pool.free_ranges_count(), 2);
+
+    // Already at the floor, so nothing more to reclaim
+    assert_eq!(pool.shrink_to(40), 0);
+
+    // A floor below the highest in-use index stops there instead
+    assert_eq!(pool.shrink_to(0), 30);
+    assert_eq!(pool.maximum(), 10);
+    assert_eq!(pool.free_ranges_count(), 1);
+}
+
+#[test]
+fn batch_defers_collapse() {
+    let mut pool = IndexPool::new();
+    for _ in 0..1000 {
+        pool.new_id();
+    }
+    pool.return_id(500).unwrap();
+
+    pool.batch(|pool| {
+        for id in (0..1000).rev().filter(|&id| id != 500) {
+            pool.return_id(id).unwrap();
+        }
+        assert_eq!(pool.in_use(), 0);
+    });
+    assert_empty(&pool);
+
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.batch(|pool| {
+        pool.return_id(3).unwrap();
+        pool.return_id(9).unwrap();
+        pool.return_id(8).unwrap();
+    });
+    assert_eq!(pool.maximum(), 8);
+    assert_eq!(pool.free_ranges_count(), 1);
+}
+
+#[test]
+fn batch_resets_after_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.batch(|pool| {
+            pool.return_id(8).unwrap();
+            pool.return_id(9).unwrap();
+            panic!("interrupted");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(pool.maximum(), 8);
+
+    // Collapsing is no longer deferred
+    pool.return_id(7).unwrap();
+    assert_eq!(pool.maximum(), 7);
+}
+
+#[test]
+fn collapse_only_large_tails() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..100 {
+        pool.new_id();
+    }
+
+    for id in 97..100 {
+        pool.return_id(id).unwrap();
+    }
+    assert_eq!(pool.collapse_if_below(10), 0);
+    assert_eq!(pool.maximum(), 100);
+
+    for id in 80..97 {
+        pool.return_id(id).unwrap();
+    }
+    assert_eq!(pool.collapse_if_below(10), 20);
+    assert_eq!(pool.maximum(), 80);
+    assert_eq!(pool.free_ranges_count(), 0);
+
+    // Nothing left to reclaim
+    assert_eq!(pool.collapse_if_below(0), 0);
+}
+
+#[test]
+fn double_return_inside_batch() {
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+
+    pool.batch(|pool| {
+        pool.return_id(8).unwrap();
+        pool.return_id(9).unwrap();
+        assert!(pool.return_id(8).is_err());
+        assert!(pool.return_id(9).is_err());
+    });
+    assert_eq!(pool.in_use(), 8);
+    assert_eq!(pool.maximum(), 8);
+}
+
+#[test]
+fn return_top_after_shrink_with_floor() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    for id in 4..10 {
+        pool.return_id(id).unwrap();
+    }
+    assert_eq!(pool.shrink_to(6), 4);
+    pool.set_auto_collapse(true);
+    assert_eq!(pool.maximum(), 4);
+    assert!(pool.return_id(5).is_err());
+    assert_eq!(pool.in_use(), 4);
+
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.batch(|pool| {
+        for id in 4..10 {
+            pool.return_id(id).unwrap();
+        }
+        assert_eq!(pool.shrink_to(6), 4);
+        assert!(pool.return_id(5).is_err());
+    });
+    assert_eq!(pool.in_use(), 4);
+    assert_eq!(pool.maximum(), 4);
+}
+
+#[test]
+fn align_tail_pads_with_free_indices() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..5 {
+        pool.new_id();
+    }
+
+    pool.align_tail(8);
+    assert_eq!(pool.maximum(), 8);
+    assert_eq!(pool.in_use(), 5);
+    assert!(pool.is_free_range(Range { min: 5, max: 7 }));
+    assert!(pool.check_range_invariants());
+
+    // Already aligned
+    pool.align_tail(4);
+    assert_eq!(pool.maximum(), 8);
+
+    // A fresh block now starts on the boundary
+    assert_eq!(pool.request_id_tracked(8), Ok(0));
+
+    // The padding is still handed out, lowest first
+    assert_eq!(pool.new_id(), 5);
+}
+
+#[test]
+fn align_tail_survives_interior_returns() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..5 {
+        pool.new_id();
+    }
+
+    pool.align_tail(8);
+    pool.return_id(1).unwrap();
+    pool.return_id(4).unwrap();
+    assert_eq!(pool.maximum(), 8);
+    assert!(pool.is_free_range(Range { min: 4, max: 7 }));
+    assert_eq!(pool.request_id_tracked(8), Ok(0));
+    assert!(pool.check_range_invariants());
+}
+
+#[test]
+#[should_panic]
+fn align_tail_with_auto_collapse() {
+    let mut pool = IndexPool::new();
+    for _ in 0..5 {
+        pool.new_id();
+    }
+    pool.align_tail(8);
+}
+
+#[test]
+fn freed_tail_is_reused_without_auto_collapse() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..8 {
+        pool.new_id();
+    }
+
+    pool.return_id(7).unwrap();
+    assert_eq!(pool.maximum(), 8);
+    assert_eq!(pool.new_id(), 7);
+    assert_eq!(pool.maximum(), 8);
+
+    // A freed run at the top is reused before the tail is extended
+    for id in 5..8 {
+        pool.return_id(id).unwrap();
+    }
+    let range = pool.reserve_range_or_grow(3);
+    assert_eq!((range.min, range.max), (5, 7));
+    assert_eq!(pool.maximum(), 8);
+    assert_eq!(pool.new_id(), 8);
+    assert_eq!(pool.maximum(), 9);
+}
+
+#[test]
+fn return_all_above_without_auto_collapse() {
+    let mut pool = IndexPool::new();
+    pool.set_auto_collapse(false);
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.return_id(3).unwrap();
+    pool.return_id(4).unwrap();
+
+    assert_eq!(pool.return_all_above(5), vec![5, 6, 7, 8, 9]);
+    assert_eq!(pool.maximum(), 5);
+    assert_eq!(pool.in_use(), 3);
+    // Only the tail above the threshold is folded in
+    assert!(pool.is_free_range(Range { min: 3, max: 4 }));
+
+    let mut pool = IndexPool::new();
+    for _ in 0..10 {
+        pool.new_id();
+    }
+    pool.batch(|pool| {
+        assert_eq!(pool.return_all_above(5).len(), 5);
+        assert_eq!(pool.maximum(), 5);
+        pool.return_id(4).unwrap();
+    });
+    assert_eq!(pool.maximum(), 4);
+    assert_eq!(pool.in_use(), 4);
+}
@@ -0,0 +1,117 @@
+extern crate index_pool;
+use index_pool::{FreeRanges, IndexPool, InvalidState, Range};
+
+use std::mem::size_of;
+
+fn to_bytes(ranges: &[(usize, usize)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for &(min, max) in ranges {
+        bytes.extend_from_slice(&min.to_ne_bytes());
+        bytes.extend_from_slice(&max.to_ne_bytes());
+    }
+    bytes
+}
+
+fn from_bytes(bytes: &[u8]) -> Vec<(usize, usize)> {
+    let word = |chunk: &[u8]| {
+        let mut buf = [0; size_of::<usize>()];
+        buf.copy_from_slice(chunk);
+        usize::from_ne_bytes(buf)
+    };
+    bytes
+        .chunks(2 * size_of::<usize>())
+        .map(|pair| {
+            let (min, max) = pair.split_at(size_of::<usize>());
+            (word(min), word(max))
+        })
+        .collect()
+}
+
+#[test]
+fn ranges_round_trip() {
+    let mut pool = IndexPool::new();
+    for _ in 0..20 {
+        pool.new_id();
+    }
+    for id in &[0, 1, 5, 9, 10, 11, 17] {
+        pool.return_id(*id).unwrap();
+    }
+
+    let mut ranges = Vec::new();
+    pool.write_ranges_to(&mut ranges);
+    assert_eq!(ranges, vec![(0, 1), (5, 5), (9, 11), (17, 17)]);
+
+    let bytes = to_bytes(&ranges);
+    let copy = IndexPool::read_ranges_from(pool.maximum(), &from_bytes(&bytes)).unwrap();
+    assert!(copy.same_free_set(&pool));
+    assert_eq!(copy.in_use(), pool.in_use());
+    assert!(copy.all_indices().eq(pool.all_indices()));
+}
+
+#[test]
+fn read_ranges_validates() {
+    assert_eq!(
+        IndexPool::read_ranges_from(10, &[(4, 5), (1, 2)]).err(),
+        Some(InvalidState)
+    );
+    assert_eq!(
+        IndexPool::read_ranges_from(10, &[(1, 4), (4, 5)]).err(),
+        Some(InvalidState)
+    );
+    assert_eq!(
+        IndexPool::read_ranges_from(10, &[(3, 2)]).err(),
+        Some(InvalidState)
+    );
+    assert_eq!(
+        IndexPool::read_ranges_from(10, &[(8, 10)]).err(),
+        Some(InvalidState)
+    );
+
+    // A trailing free range is folded into the tail
+    let pool = IndexPool::read_ranges_from(10, &[(2, 3), (7, 9)]).unwrap();
+    assert_eq!(pool.maximum(), 7);
+    assert_eq!(pool.in_use(), 5);
+}
+
+#[test]
+fn from_raw_parts_matches_public_api() {
+    let mut expected = IndexPool::new();
+    for _ in 0..12 {
+        expected.new_id();
+    }
+    for id in (2..5).chain(Some(7)) {
+        expected.return_id(id).unwrap();
+    }
+
+    let mut ranges = FreeRanges::new();
+    ranges.set_range_free(Range { min: 2, max: 4 });
+    ranges.set_free(7);
+    let pool = IndexPool::from_raw_parts(12, ranges).unwrap();
+
+    assert_eq!(pool.maximum(), expected.maximum());
+    assert_eq!(pool.in_use(), expected.in_use());
+    assert!(pool.same_free_set(&expected));
+    assert!(pool.all_indices().eq(expected.all_indices()));
+
+    // Round trip through into_free_ranges
+    let (next_id, ranges) = pool.into_free_ranges();
+    let pool = IndexPool::from_raw_parts(next_id, ranges).unwrap();
+    assert!(pool.same_free_set(&expected));
+}
+
+#[test]
+fn from_raw_parts_rejects_bad_state() {
+    let mut ranges = FreeRanges::new();
+    ranges.set_range_free(Range { min: 5, max: 9 });
+    assert_eq!(
+        IndexPool::from_raw_parts(8, ranges).err(),
+        Some(InvalidState)
+    );
+
+    // A free tail is folded back in
+    let mut ranges = FreeRanges::new();
+    ranges.set_range_free(Range { min: 5, max: 9 });
+    let pool = IndexPool::from_raw_parts(10, ranges).unwrap();
+    assert_eq!(pool.maximum(), 5);
+    assert_eq!(pool.in_use(), 5);
+}
@@ -0,0 +1,95 @@
+extern crate index_pool;
+use index_pool::backend::VecFreeRanges;
+use index_pool::{FreeListBackend, FreeRanges, IndexPool};
+
+use std::collections::BTreeSet;
+
+/// A tiny xorshift generator so the operation sequences are reproducible
+/// without pulling in a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn check_consistent<B: FreeListBackend>(pool: &IndexPool<B>, live: &BTreeSet<usize>) {
+    pool.assert_ranges_sorted();
+    let stats = pool.stats();
+    assert_eq!(pool.in_use(), pool.maximum() - stats.free_count);
+    assert_eq!(pool.in_use(), live.len());
+    assert_eq!(
+        pool.maximum(),
+        live.iter().next_back().map_or(0, |&id| id + 1)
+    );
+
+    let yielded: Vec<usize> = pool.all_indices().collect();
+    assert!(yielded.iter().eq(live.iter()));
+    for id in 0..pool.maximum() + 2 {
+        assert_eq!(pool.is_free(id), !live.contains(&id));
+    }
+}
+
+fn run_ops<B: FreeListBackend>(seed: u64, auto_collapse: bool) {
+    let mut rng = Rng(seed);
+    let mut pool = IndexPool::<B>::with_backend();
+    pool.set_auto_collapse(auto_collapse);
+    let mut live = BTreeSet::new();
+
+    for _ in 0..500 {
+        match rng.below(3) {
+            0 => {
+                let id = pool.new_id();
+                assert!(live.insert(id), "new_id returned live id {}", id);
+            }
+            1 => {
+                let id = rng.below(64);
+                let result = pool.request_id(id);
+                assert_eq!(result.is_ok(), live.insert(id));
+            }
+            _ => {
+                let id = rng.below(64);
+                let result = pool.return_id(id);
+                assert_eq!(result.is_ok(), live.remove(&id));
+            }
+        }
+
+        if auto_collapse {
+            check_consistent(&pool, &live);
+        } else {
+            let mut collapsed = pool.clone();
+            collapsed.collapse_tail();
+            check_consistent(&collapsed, &live);
+        }
+    }
+}
+
+#[test]
+fn random_operations_stay_consistent() {
+    for seed in 1..200u64 {
+        run_ops::<FreeRanges>(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), true);
+    }
+}
+
+#[test]
+fn random_operations_without_auto_collapse() {
+    for seed in 1..200u64 {
+        run_ops::<FreeRanges>(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), false);
+    }
+}
+
+#[test]
+fn random_operations_with_vec_backend() {
+    for seed in 1..200u64 {
+        run_ops::<VecFreeRanges>(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), true);
+        run_ops::<VecFreeRanges>(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), false);
+    }
+}
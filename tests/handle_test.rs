@@ -0,0 +1,24 @@
+extern crate index_pool;
+use index_pool::{IndexPool, PoolHandle};
+
+#[test]
+fn clones_share_pool() {
+    let a = PoolHandle::new();
+    let b = a.clone();
+    assert!(a.same_pool(&b));
+
+    let id = a.new_id();
+    assert!(!b.is_free(id));
+    assert_eq!(b.in_use(), 1);
+    assert_eq!(b.maximum(), 1);
+
+    b.return_id(id).unwrap();
+    assert!(a.is_free(id));
+    assert_eq!(a.in_use(), 0);
+
+    assert_eq!(b.request_id(4), Ok(()));
+    assert!(a.borrow().all_indices().eq(Some(4)));
+
+    let other = PoolHandle::from(IndexPool::new());
+    assert!(!other.same_pool(&a));
+}
@@ -0,0 +1,63 @@
+extern crate index_pool;
+use index_pool::{IndexPool, PoolHandle};
+
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn block_lease_frees_on_drop() {
+    let mut pool = IndexPool::new();
+    pool.new_id();
+
+    {
+        let mut lease = pool.lease_contiguous(4);
+        assert_eq!((lease.min, lease.max), (1, 4));
+        assert_eq!(lease.pool().new_id(), 5);
+    }
+    assert!(pool.is_free_range(index_pool::Range { min: 1, max: 4 }));
+    assert_eq!(pool.in_use(), 2);
+
+    let kept = pool.lease_contiguous(2).into_range();
+    assert_eq!((kept.min, kept.max), (1, 2));
+    assert!(!pool.is_free(1) && !pool.is_free(2));
+}
+
+#[test]
+fn block_lease_frees_once() {
+    let mut pool = IndexPool::new();
+    {
+        let mut lease = pool.lease_contiguous(3);
+        lease.pool().return_id(1).unwrap();
+    }
+    assert_eq!(pool.in_use(), 0);
+    assert_eq!(pool.maximum(), 0);
+}
+
+#[test]
+fn block_lease_frees_on_panic() {
+    let mut pool = IndexPool::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _lease = pool.lease_contiguous(8);
+        panic!("early exit");
+    }));
+    assert!(result.is_err());
+    assert_eq!(pool.in_use(), 0);
+}
+
+#[test]
+fn shared_block_lease() {
+    let handle = PoolHandle::new();
+    let lease = handle.lease_contiguous(5);
+    assert_eq!((lease.min, lease.max), (0, 4));
+
+    // The pool is still usable through the handle while leased
+    assert_eq!(handle.new_id(), 5);
+    assert_eq!(handle.in_use(), 6);
+
+    drop(lease);
+    assert_eq!(handle.in_use(), 1);
+    assert!(handle.is_free(0));
+
+    let kept = handle.lease_contiguous(2).into_range();
+    assert_eq!((kept.min, kept.max), (0, 1));
+    assert_eq!(handle.in_use(), 3);
+}
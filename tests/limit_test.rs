@@ -0,0 +1,99 @@
+extern crate index_pool;
+use index_pool::{IndexPool, PoolFull, RequestError};
+
+#[test]
+fn bounded_pool() {
+    let mut pool = IndexPool::with_limit(3);
+    assert_eq!(pool.limit(), Some(3));
+    assert_eq!(IndexPool::new().limit(), None);
+
+    assert_eq!(pool.try_new_id(), Ok(0));
+    assert_eq!(pool.try_new_id(), Ok(1));
+    assert_eq!(pool.try_new_id(), Ok(2));
+    assert_eq!(pool.try_new_id(), Err(PoolFull));
+    assert_eq!(pool.in_use(), 3);
+    assert_eq!(pool.maximum(), 3);
+
+    pool.return_id(1).unwrap();
+    assert_eq!(pool.first_n_free(5), vec![1]);
+    assert_eq!(pool.try_new_id(), Ok(1));
+}
+
+#[test]
+fn checked_request_respects_limit() {
+    let mut pool = IndexPool::with_limit(10);
+
+    assert_eq!(pool.checked_request_id(10), Err(RequestError::OutOfBounds));
+    assert_eq!(pool.checked_request_id(100), Err(RequestError::OutOfBounds));
+    assert_eq!(pool.maximum(), 0);
+    assert_eq!(pool.in_use(), 0);
+
+    assert_eq!(pool.checked_request_id(9), Ok(()));
+    assert_eq!(pool.checked_request_id(9), Err(RequestError::AlreadyInUse));
+    assert_eq!(pool.maximum(), 10);
+}
+
+#[test]
+#[should_panic]
+fn request_beyond_limit_panics() {
+    let mut pool = IndexPool::with_limit(4);
+    let _ = pool.request_id(4);
+}
+
+#[test]
+fn reserve_highest() {
+    let mut pool = IndexPool::with_limit(6);
+
+    assert_eq!(pool.reserve_highest(), Some(5));
+    assert_eq!(pool.reserve_highest(), Some(4));
+    assert_eq!(pool.new_id(), 0);
+    assert_eq!(pool.reserve_highest(), Some(3));
+    assert_eq!(pool.reserve_highest(), Some(2));
+    assert_eq!(pool.reserve_highest(), Some(1));
+    assert_eq!(pool.reserve_highest(), None);
+    assert_eq!(pool.in_use(), 6);
+
+    pool.return_id(4).unwrap();
+    assert_eq!(pool.reserve_highest(), Some(4));
+
+    assert_eq!(IndexPool::new().reserve_highest(), None);
+}
+
+#[test]
+fn windowed_pool() {
+    let mut pool = IndexPool::windowed(10, 13);
+    assert_eq!(pool.try_new_id(), Ok(10));
+    assert_eq!(pool.try_new_id(), Ok(11));
+    assert_eq!(pool.try_new_id(), Ok(12));
+    assert_eq!(pool.try_new_id(), Err(PoolFull));
+
+    pool.return_id(11).unwrap();
+    assert_eq!(pool.try_new_id(), Ok(11));
+
+    assert_eq!(pool.checked_request_id(13), Err(RequestError::OutOfBounds));
+    assert_eq!(pool.checked_request_id(3), Err(RequestError::AlreadyInUse));
+}
+
+#[test]
+fn align_tail_past_limit() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut pool = IndexPool::with_limit(12);
+    pool.set_auto_collapse(false);
+    for _ in 0..9 {
+        pool.new_id();
+    }
+    assert!(catch_unwind(AssertUnwindSafe(|| pool.align_tail(16))).is_err());
+    assert_eq!(pool.maximum(), 9);
+    pool.align_tail(4);
+    assert_eq!(pool.maximum(), 12);
+}
+
+#[test]
+#[should_panic]
+fn reserve_tail_past_limit() {
+    let mut pool = IndexPool::with_limit(8);
+    pool.reserve_tail(6);
+    pool.return_id(0).unwrap();
+    pool.reserve_tail(3);
+}
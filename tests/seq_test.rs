@@ -0,0 +1,62 @@
+extern crate index_pool;
+use index_pool::IndexPool;
+
+#[test]
+fn allocation_seq_increases() {
+    let mut pool = IndexPool::new();
+    let early = pool.new_id();
+    assert_eq!(pool.allocation_seq(early), None);
+
+    pool.track_allocation_seq(true);
+    assert!(pool.allocation_seq(early).is_some());
+
+    let ids: Vec<usize> = (0..5).map(|_| pool.new_id()).collect();
+    let seqs: Vec<u64> = ids
+        .iter()
+        .map(|&id| pool.allocation_seq(id).unwrap())
+        .collect();
+    assert!(seqs.windows(2).all(|w| w[0] < w[1]));
+    assert!(pool.allocation_seq(early).unwrap() < seqs[0]);
+
+    pool.return_id(ids[1]).unwrap();
+    assert_eq!(pool.allocation_seq(ids[1]), None);
+
+    // Reusing a hole tags it as the newest allocation
+    assert_eq!(pool.new_id(), ids[1]);
+    assert!(pool.allocation_seq(ids[1]).unwrap() > seqs[4]);
+
+    pool.request_id(20).unwrap();
+    assert!(pool.allocation_seq(20).unwrap() > pool.allocation_seq(ids[1]).unwrap());
+    assert_eq!(pool.allocation_seq(19), None);
+
+    pool.track_allocation_seq(false);
+    assert_eq!(pool.allocation_seq(20), None);
+}
+
+#[test]
+fn oldest_and_newest() {
+    let mut pool = IndexPool::new();
+    assert_eq!(pool.oldest_in_use(), None);
+
+    pool.track_allocation_seq(true);
+    assert_eq!(pool.oldest_in_use(), None);
+    assert_eq!(pool.newest_in_use(), None);
+
+    for _ in 0..5 {
+        pool.new_id();
+    }
+    assert_eq!(pool.oldest_in_use(), Some(0));
+    assert_eq!(pool.newest_in_use(), Some(4));
+
+    pool.return_id(0).unwrap();
+    pool.return_id(1).unwrap();
+    assert_eq!(pool.oldest_in_use(), Some(2));
+
+    // 0 is reused, which makes it the newest rather than the oldest
+    assert_eq!(pool.new_id(), 0);
+    assert_eq!(pool.oldest_in_use(), Some(2));
+    assert_eq!(pool.newest_in_use(), Some(0));
+
+    pool.return_id(0).unwrap();
+    assert_eq!(pool.newest_in_use(), Some(4));
+}
@@ -0,0 +1,56 @@
+extern crate index_pool;
+use index_pool::IndexPool;
+
+fn pool_with(ids: &[usize]) -> IndexPool {
+    let mut pool = IndexPool::new();
+    for &id in ids {
+        pool.request_id(id).unwrap();
+    }
+    pool
+}
+
+#[test]
+fn difference_and_intersection() {
+    let a = pool_with(&[0, 1, 2, 3, 7, 8, 9, 15, 20]);
+    let b = pool_with(&[2, 3, 4, 5, 8, 16, 17]);
+
+    assert_eq!(
+        a.indices_difference(&b).collect::<Vec<_>>(),
+        vec![0, 1, 7, 9, 15, 20]
+    );
+    assert_eq!(
+        b.indices_difference(&a).collect::<Vec<_>>(),
+        vec![4, 5, 16, 17]
+    );
+    assert_eq!(
+        a.indices_intersection(&b).collect::<Vec<_>>(),
+        vec![2, 3, 8]
+    );
+
+    let empty = IndexPool::new();
+    assert!(a.indices_difference(&empty).eq(a.all_indices()));
+    assert_eq!(a.indices_intersection(&empty).count(), 0);
+    assert!(a.indices_intersection(&a).eq(a.all_indices()));
+}
+
+#[test]
+fn symmetric_difference() {
+    let mut before = IndexPool::new();
+    for _ in 0..10 {
+        before.new_id();
+    }
+    before.return_id(4).unwrap();
+
+    let mut after = before.clone();
+    after.return_id(2).unwrap();
+    after.return_id(3).unwrap();
+    after.request_id(4).unwrap();
+    after.request_id(12).unwrap();
+
+    let changed: Vec<_> = before.indices_symmetric_difference(&after).collect();
+    assert_eq!(changed, vec![2, 3, 4, 12]);
+    assert!(after
+        .indices_symmetric_difference(&before)
+        .eq(changed.iter().cloned()));
+    assert_eq!(before.indices_symmetric_difference(&before).count(), 0);
+}
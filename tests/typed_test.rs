@@ -0,0 +1,30 @@
+extern crate index_pool;
+use index_pool::typed::{Index, TypedIndexPool};
+
+struct Entity;
+struct Component;
+
+#[test]
+fn separate_id_spaces() {
+    let mut entities = TypedIndexPool::<Entity>::new();
+    let mut components = TypedIndexPool::<Component>::new();
+
+    let e0 = entities.new_id();
+    let e1 = entities.new_id();
+    let c0 = components.new_id();
+
+    assert_eq!(e0.raw(), 0);
+    assert_eq!(e1.raw(), 1);
+    assert_eq!(c0.raw(), 0);
+    assert_eq!(e1, Index::from_raw(1));
+
+    entities.return_id(e0).unwrap();
+    assert!(entities.is_free(e0));
+    assert!(!components.is_free(c0));
+    assert!(entities.all_indices().eq(Some(e1)));
+
+    assert_eq!(entities.request_id(Index::from_raw(4)), Ok(()));
+    assert_eq!(entities.maximum(), 5);
+    assert_eq!(entities.in_use(), 2);
+    assert_eq!(entities.as_pool().in_use(), 2);
+}